@@ -346,7 +346,7 @@ async fn wait_for_docker_health(container: &str, timeout_secs: u64) -> Result<()
 
     for _ in 0..timeout_secs {
         let output = Command::new("docker")
-            .args(&["inspect", "--format", "{{.State.Health.Status}}", container])
+            .args(["inspect", "--format", "{{.State.Health.Status}}", container])
             .output();
 
         if let Ok(out) = output {
@@ -0,0 +1,225 @@
+use crate::engine::EngineEvent;
+use crate::journal::JournalWriter;
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender, TrySendError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// What the engine does when a bounded event channel is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Backpressure {
+    /// Wait for the reader; nothing is lost but the run slows to the reader's pace.
+    #[default]
+    Block,
+    /// Evict the oldest queued event to make room.
+    DropOldest,
+    /// Keep only the latest Bar (per symbol), Portfolio and Metrics while the reader lags;
+    /// every other event is delivered, blocking if needed.
+    Coalesce,
+}
+
+/// Events lost to backpressure, shared between the engine and its handle.
+#[derive(Debug, Default)]
+pub struct DropCounters {
+    /// Events evicted under `DropOldest`.
+    pub evicted: AtomicU64,
+    /// Events superseded by a newer one under `Coalesce`.
+    pub coalesced: AtomicU64,
+}
+
+impl DropCounters {
+    pub fn total(&self) -> u64 {
+        self.evicted.load(Ordering::Relaxed) + self.coalesced.load(Ordering::Relaxed)
+    }
+}
+
+/// Engine side of the event channel, applying the configured backpressure policy.
+pub struct EventSender {
+    tx: Sender<EngineEvent>,
+    /// Evicts from the front of a bounded queue under `DropOldest`. Only held then, since
+    /// it keeps the channel from disconnecting; eviction keeps that queue bounded anyway.
+    evict: Option<Receiver<EngineEvent>>,
+    /// Every reader is gone, so events are only journaled.
+    closed: bool,
+    policy: Backpressure,
+    drops: Arc<DropCounters>,
+    /// Coalesced events waiting for room, oldest first, at most one per key.
+    pending: Vec<EngineEvent>,
+    /// Records every event, including those the reader never sees.
+    journal: Option<JournalWriter>,
+}
+
+/// Creates the event channel; `capacity: None` keeps it unbounded.
+pub fn event_channel(
+    capacity: Option<usize>,
+    policy: Backpressure,
+) -> (EventSender, Receiver<EngineEvent>, Arc<DropCounters>) {
+    let (tx, rx) = match capacity {
+        Some(cap) => bounded(cap),
+        None => unbounded(),
+    };
+    let drops = Arc::new(DropCounters::default());
+    let bounded_drop_oldest = capacity.is_some() && policy == Backpressure::DropOldest;
+    let sender = EventSender {
+        tx,
+        evict: bounded_drop_oldest.then(|| rx.clone()),
+        closed: false,
+        policy,
+        drops: drops.clone(),
+        pending: Vec::new(),
+        journal: None,
+    };
+    (sender, rx, drops)
+}
+
+impl EventSender {
+    pub fn set_journal(&mut self, journal: JournalWriter) {
+        self.journal = Some(journal);
+    }
+
+    /// Sequence number the next event is journaled under, while a journal is recording.
+    pub fn journal_seq(&self) -> Option<u64> {
+        self.journal.as_ref().map(|j| j.next_seq())
+    }
+
+    /// Sends `evt` under the backpressure policy. Once the reader is gone events are
+    /// still journaled but no longer queued.
+    pub fn send(&mut self, evt: EngineEvent) {
+        if let Some(journal) = &mut self.journal {
+            if let Err(e) = journal.append(&evt) {
+                // The run itself can go on without its journal
+                tracing::warn!("journal write failed, recording stopped: {e}");
+                self.journal = None;
+            }
+        }
+        if self.closed {
+            return;
+        }
+        match self.policy {
+            Backpressure::Block => self.send_blocking(evt),
+            Backpressure::DropOldest => {
+                let mut evt = evt;
+                loop {
+                    match self.tx.try_send(evt) {
+                        Ok(()) => return,
+                        Err(TrySendError::Disconnected(_)) => {
+                            self.closed = true;
+                            return;
+                        }
+                        Err(TrySendError::Full(back)) => {
+                            evt = back;
+                            let evict = self.evict.as_ref().expect("bounded DropOldest evicts");
+                            if evict.try_recv().is_ok() {
+                                self.drops.evicted.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                }
+            }
+            Backpressure::Coalesce => {
+                if !self.flush_pending() {
+                    // Still lagging: keep ordering by queueing behind what is pending
+                    if coalesce_key(&evt).is_some() {
+                        self.stash(evt);
+                    } else {
+                        self.flush_blocking();
+                        self.send_blocking(evt);
+                    }
+                    return;
+                }
+                match self.tx.try_send(evt) {
+                    Ok(()) => {}
+                    Err(TrySendError::Disconnected(_)) => self.closed = true,
+                    Err(TrySendError::Full(evt)) => {
+                        if coalesce_key(&evt).is_some() {
+                            self.stash(evt);
+                        } else {
+                            self.send_blocking(evt);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves pending events into the channel while there is room; true once none are left.
+    fn flush_pending(&mut self) -> bool {
+        while !self.pending.is_empty() {
+            let evt = self.pending.remove(0);
+            match self.tx.try_send(evt) {
+                Ok(()) => {}
+                Err(TrySendError::Disconnected(_)) => {
+                    self.pending.clear();
+                    self.closed = true;
+                }
+                Err(TrySendError::Full(evt)) => {
+                    self.pending.insert(0, evt);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn flush_blocking(&mut self) {
+        for evt in std::mem::take(&mut self.pending) {
+            self.send_blocking(evt);
+        }
+    }
+
+    fn send_blocking(&mut self, evt: EngineEvent) {
+        if !self.closed && self.tx.send(evt).is_err() {
+            self.closed = true;
+        }
+    }
+
+    fn stash(&mut self, evt: EngineEvent) {
+        let key = coalesce_key(&evt);
+        if let Some(i) = self.pending.iter().position(|p| coalesce_key(p) == key) {
+            self.pending.remove(i);
+            self.drops.coalesced.fetch_add(1, Ordering::Relaxed);
+        }
+        self.pending.push(evt);
+    }
+}
+
+/// Events that may be superseded by a newer one of the same key.
+fn coalesce_key(evt: &EngineEvent) -> Option<(u8, &str)> {
+    match evt {
+        EngineEvent::Bar(b) => Some((0, b.symbol.as_str())),
+        EngineEvent::Portfolio(_) => Some((1, "")),
+        EngineEvent::Metrics(_) => Some((2, "")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::RunStatus;
+    use std::time::Duration;
+
+    #[test]
+    fn senders_stop_queueing_once_the_reader_is_gone() {
+        let policies = [Backpressure::Block, Backpressure::DropOldest, Backpressure::Coalesce];
+        for capacity in [Some(4), None] {
+            for policy in policies {
+                let (mut sender, rx, _) = event_channel(capacity, policy);
+                drop(rx);
+                // A blocking send to a dropped reader would never return
+                let (done, finished) = crossbeam_channel::bounded(1);
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        sender.send(EngineEvent::Done(RunStatus::Completed));
+                    }
+                    done.send(sender.tx.len()).unwrap();
+                });
+                let queued = finished.recv_timeout(Duration::from_secs(5));
+                let queued = queued.unwrap_or_else(|_| panic!("{policy:?} {capacity:?} blocked"));
+                // Bounded DropOldest keeps evicting; every other channel is disconnected
+                let expected =
+                    if capacity.is_some() && policy == Backpressure::DropOldest { 4 } else { 0 };
+                assert_eq!(queued, expected, "{policy:?} {capacity:?}");
+            }
+        }
+    }
+}
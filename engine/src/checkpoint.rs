@@ -0,0 +1,38 @@
+use crate::engine::Account;
+use crate::types::EngineConfig;
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+/// Engine state between two time steps. The run's bars are not stored: `Engine::resume`
+/// regenerates them from `seed` and `start` and carries on at `cursor`.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Checkpoint {
+    pub config: EngineConfig,
+    /// Seed and start of the generated data, resolved when the run began.
+    pub seed: u64,
+    pub start: DateTime<Utc>,
+    /// Index of the next time step to run.
+    pub cursor: usize,
+    /// Model state from `Strategy::state`.
+    pub strategy: serde_json::Value,
+    /// Working orders from `ExecutionModel::state`.
+    pub execution: serde_json::Value,
+    /// Sequence number of the first journal entry after this checkpoint, when the run
+    /// keeps a journal. Resuming cuts the journal back to it.
+    #[serde(default)]
+    pub journal_seq: Option<u64>,
+    pub(crate) account: Account,
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+}
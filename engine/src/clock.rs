@@ -0,0 +1,79 @@
+use crate::types::*;
+use chrono::{DateTime, Utc};
+use std::time::{Duration, Instant};
+
+/// Decides when each time step of a run is released, which is what separates the modes.
+pub trait Clock: Send {
+    /// Time left before the step stamped `ts` is due at playback `speed`.
+    fn remaining(&self, ts: DateTime<Utc>, speed: f64) -> Duration;
+
+    /// Records that the step stamped `ts` has been released.
+    fn tick(&mut self, _ts: DateTime<Utc>) {}
+}
+
+/// Backtest, and Forwardtest, whose feed already waits for each bar to close: every step
+/// is due as soon as it is there.
+pub struct InstantClock;
+
+impl Clock for InstantClock {
+    fn remaining(&self, _ts: DateTime<Utc>, _speed: f64) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// Simulate: one step per `interval` of wall time, scaled by the playback speed.
+pub struct PacedClock {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl PacedClock {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last: None }
+    }
+}
+
+impl Clock for PacedClock {
+    fn remaining(&self, _ts: DateTime<Utc>, speed: f64) -> Duration {
+        match self.last {
+            Some(last) => {
+                (last + self.interval.div_f64(speed)).saturating_duration_since(Instant::now())
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    fn tick(&mut self, _ts: DateTime<Utc>) {
+        self.last = Some(Instant::now());
+    }
+}
+
+/// Replay: steps are released as far apart as they were recorded, scaled by the
+/// playback speed.
+#[derive(Default)]
+pub struct ReplayClock {
+    last: Option<(Instant, DateTime<Utc>)>,
+}
+
+impl Clock for ReplayClock {
+    fn remaining(&self, ts: DateTime<Utc>, speed: f64) -> Duration {
+        match self.last {
+            Some((at, last_ts)) => {
+                let gap = (ts - last_ts).to_std().unwrap_or(Duration::ZERO);
+                (at + gap.div_f64(speed)).saturating_duration_since(Instant::now())
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    fn tick(&mut self, ts: DateTime<Utc>) {
+        self.last = Some((Instant::now(), ts));
+    }
+}
+
+pub fn clock_for(mode: &Mode, interval: Duration) -> Box<dyn Clock> {
+    match mode {
+        Mode::Simulate => Box::new(PacedClock::new(interval)),
+        Mode::Backtest | Mode::Forwardtest => Box::new(InstantClock),
+    }
+}
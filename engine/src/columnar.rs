@@ -0,0 +1,511 @@
+use crate::data::DataSource;
+use crate::history::check_bar;
+use crate::report::*;
+use crate::types::*;
+use arrow::array::{
+    Array, ArrayRef, AsArray, Float64Array, Int64Array, RecordBatch, StringArray,
+    TimestampNanosecondArray, UInt64Array,
+};
+use arrow::compute::cast;
+use arrow::datatypes::{
+    DataType, Field, Float64Type, Int64Type, Schema, TimeUnit, TimestampNanosecondType, UInt64Type,
+};
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use chrono::{DateTime, Utc};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Bars from Parquet or Arrow IPC files with the columns `write_bars` produces. Other
+/// columns are ignored, and numbers and timestamps of any width are accepted, so files
+/// written by Polars or pandas load as they are. Bars are checked like CSV rows, and the
+/// first bad one fails the load.
+pub struct ColumnarSource {
+    pub paths: Vec<String>,
+}
+
+impl ColumnarSource {
+    pub fn new(paths: Vec<String>) -> Self {
+        Self { paths }
+    }
+}
+
+impl DataSource for ColumnarSource {
+    fn load(&mut self, symbols: &[String]) -> anyhow::Result<Vec<Vec<Bar>>> {
+        // A file without a symbol column holds the run's only symbol
+        let only = match symbols {
+            [only] => Some(only.as_str()),
+            _ => None,
+        };
+        let mut series: HashMap<String, Vec<Bar>> = HashMap::new();
+        for path in &self.paths {
+            for bar in read_bars_of(Path::new(path), only)? {
+                if symbols.contains(&bar.symbol) {
+                    series.entry(bar.symbol.clone()).or_default().push(bar);
+                }
+            }
+        }
+        symbols
+            .iter()
+            .map(|symbol| {
+                let mut bars = series
+                    .remove(symbol)
+                    .ok_or_else(|| anyhow::anyhow!("no bars for symbol {symbol}"))?;
+                bars.sort_by_key(|b| b.ts);
+                if let Some(w) = bars.windows(2).find(|w| w[0].ts == w[1].ts) {
+                    anyhow::bail!("two {symbol} bars at {}", w[0].ts);
+                }
+                Ok(bars)
+            })
+            .collect()
+    }
+}
+
+pub fn write_bars(path: impl AsRef<Path>, bars: &[Bar]) -> anyhow::Result<()> {
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        ("ts", timestamps(bars.iter().map(|b| b.ts))),
+        ("symbol", Arc::new(StringArray::from_iter_values(bars.iter().map(|b| &b.symbol)))),
+        ("open", floats(bars.iter().map(|b| b.open))),
+        ("high", floats(bars.iter().map(|b| b.high))),
+        ("low", floats(bars.iter().map(|b| b.low))),
+        ("close", floats(bars.iter().map(|b| b.close))),
+        ("volume", floats(bars.iter().map(|b| b.volume))),
+        ("regime", Arc::new(bars.iter().map(|b| b.regime.as_deref()).collect::<StringArray>())),
+    ];
+    write_batch(path.as_ref(), columns)
+}
+
+pub fn read_bars(path: impl AsRef<Path>) -> anyhow::Result<Vec<Bar>> {
+    read_bars_of(path.as_ref(), None)
+}
+
+fn read_bars_of(path: &Path, symbol: Option<&str>) -> anyhow::Result<Vec<Bar>> {
+    let mut bars = Vec::new();
+    for batch in read_batches(path)? {
+        let columns = Columns { batch: &batch, path };
+        let ts = columns.timestamps("ts")?;
+        let symbols = match (batch.column_by_name("symbol"), symbol) {
+            (None, Some(symbol)) => vec![symbol.to_string(); batch.num_rows()],
+            _ => columns.strings("symbol")?,
+        };
+        let open = columns.floats("open")?;
+        let high = columns.floats("high")?;
+        let low = columns.floats("low")?;
+        let close = columns.floats("close")?;
+        let volume = columns.floats("volume")?;
+        let regime = columns.optional_strings("regime")?;
+        for (i, symbol) in symbols.into_iter().enumerate() {
+            let bar = Bar {
+                ts: ts[i],
+                open: open[i],
+                high: high[i],
+                low: low[i],
+                close: close[i],
+                volume: volume[i],
+                symbol,
+                regime: regime.as_ref().and_then(|r| r[i].clone()),
+            };
+            check_bar(&bar)
+                .map_err(|e| anyhow::anyhow!("{}: row {}: {e}", path.display(), bars.len() + 1))?;
+            bars.push(bar);
+        }
+    }
+    Ok(bars)
+}
+
+pub fn write_trades(path: impl AsRef<Path>, trades: &[RoundTrip]) -> anyhow::Result<()> {
+    let side = |s: Side| match s {
+        Side::Buy => "Buy",
+        Side::Sell => "Sell",
+    };
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        ("symbol", Arc::new(StringArray::from_iter_values(trades.iter().map(|t| &t.symbol)))),
+        ("side", Arc::new(StringArray::from_iter_values(trades.iter().map(|t| side(t.side))))),
+        ("qty", floats(trades.iter().map(|t| t.qty))),
+        ("entry_ts", timestamps(trades.iter().map(|t| t.entry_ts))),
+        ("entry_price", floats(trades.iter().map(|t| t.entry_price))),
+        ("exit_ts", timestamps(trades.iter().map(|t| t.exit_ts))),
+        ("exit_price", floats(trades.iter().map(|t| t.exit_price))),
+        ("pnl", floats(trades.iter().map(|t| t.pnl))),
+        ("fees", floats(trades.iter().map(|t| t.fees))),
+        ("holding_ms", Arc::new(Int64Array::from_iter_values(trades.iter().map(|t| t.holding_ms)))),
+    ];
+    write_batch(path.as_ref(), columns)
+}
+
+pub fn read_trades(path: impl AsRef<Path>) -> anyhow::Result<Vec<RoundTrip>> {
+    let path = path.as_ref();
+    let mut trades = Vec::new();
+    for batch in read_batches(path)? {
+        let columns = Columns { batch: &batch, path };
+        let side = columns.strings("side")?;
+        let qty = columns.floats("qty")?;
+        let entry_ts = columns.timestamps("entry_ts")?;
+        let entry_price = columns.floats("entry_price")?;
+        let exit_ts = columns.timestamps("exit_ts")?;
+        let exit_price = columns.floats("exit_price")?;
+        let pnl = columns.floats("pnl")?;
+        let fees = columns.floats("fees")?;
+        let holding_ms = columns.integers("holding_ms")?;
+        for (i, symbol) in columns.strings("symbol")?.into_iter().enumerate() {
+            let side = match side[i].as_str() {
+                "Buy" => Side::Buy,
+                "Sell" => Side::Sell,
+                other => anyhow::bail!("{}: unknown side {other}", path.display()),
+            };
+            trades.push(RoundTrip {
+                symbol,
+                side,
+                qty: qty[i],
+                entry_ts: entry_ts[i],
+                entry_price: entry_price[i],
+                exit_ts: exit_ts[i],
+                exit_price: exit_price[i],
+                pnl: pnl[i],
+                fees: fees[i],
+                holding_ms: holding_ms[i],
+            });
+        }
+    }
+    Ok(trades)
+}
+
+pub fn write_equity_curve(path: impl AsRef<Path>, curve: &[EquityPoint]) -> anyhow::Result<()> {
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        ("ts", timestamps(curve.iter().map(|p| p.ts))),
+        ("equity", floats(curve.iter().map(|p| p.equity))),
+    ];
+    write_batch(path.as_ref(), columns)
+}
+
+pub fn read_equity_curve(path: impl AsRef<Path>) -> anyhow::Result<Vec<EquityPoint>> {
+    let path = path.as_ref();
+    let mut curve = Vec::new();
+    for batch in read_batches(path)? {
+        let columns = Columns { batch: &batch, path };
+        let equity = columns.floats("equity")?;
+        for (i, ts) in columns.timestamps("ts")?.into_iter().enumerate() {
+            curve.push(EquityPoint { ts, equity: equity[i] });
+        }
+    }
+    Ok(curve)
+}
+
+/// One row with a column per metric; counts stay unsigned integers. Reading accepts
+/// counts of any integer type.
+pub fn write_metrics(path: impl AsRef<Path>, metrics: &Metrics) -> anyhow::Result<()> {
+    let serde_json::Value::Object(fields) = serde_json::to_value(metrics)? else {
+        anyhow::bail!("metrics must serialize to an object");
+    };
+    let columns = fields
+        .iter()
+        .map(|(name, value)| {
+            let column: ArrayRef = match value.as_u64() {
+                Some(n) => Arc::new(UInt64Array::from(vec![n])),
+                None => Arc::new(Float64Array::from(vec![value.as_f64()])),
+            };
+            (name.as_str(), column)
+        })
+        .collect();
+    write_batch(path.as_ref(), columns)
+}
+
+pub fn read_metrics(path: impl AsRef<Path>) -> anyhow::Result<Metrics> {
+    let path = path.as_ref();
+    let batch = read_batches(path)?
+        .into_iter()
+        .find(|b| b.num_rows() > 0)
+        .ok_or_else(|| anyhow::anyhow!("{}: no metrics row", path.display()))?;
+    let mut fields = serde_json::Map::new();
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        let columns = Columns { batch: &batch, path };
+        let value = if column.data_type().is_integer() {
+            let count = columns.required(field.name(), &DataType::UInt64)?;
+            count.as_primitive::<UInt64Type>().value(0).into()
+        } else {
+            serde_json::Value::from(columns.floats(field.name())?[0])
+        };
+        fields.insert(field.name().clone(), value);
+    }
+    Ok(serde_json::from_value(serde_json::Value::Object(fields))?)
+}
+
+impl RunReport {
+    /// Writes `trades`, `equity_curve` and `metrics` files in `format` into `dir`, which is
+    /// created if needed.
+    pub fn export(&self, dir: impl AsRef<Path>, format: ColumnarFormat) -> anyhow::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).map_err(|e| anyhow::anyhow!("{}: {e}", dir.display()))?;
+        let file = |name: &str| dir.join(format!("{name}.{}", format.extension()));
+        write_trades(file("trades"), &self.trades)?;
+        write_equity_curve(file("equity_curve"), &self.equity_curve)?;
+        write_metrics(file("metrics"), &self.metrics)
+    }
+}
+
+fn floats(values: impl Iterator<Item = f64>) -> ArrayRef {
+    Arc::new(Float64Array::from_iter_values(values))
+}
+
+fn timestamps(values: impl Iterator<Item = DateTime<Utc>>) -> ArrayRef {
+    let nanos = values.map(|ts| ts.timestamp_nanos_opt().unwrap_or(i64::MAX));
+    Arc::new(TimestampNanosecondArray::from_iter_values(nanos).with_timezone("UTC"))
+}
+
+fn write_batch(path: &Path, columns: Vec<(&str, ArrayRef)>) -> anyhow::Result<()> {
+    let format = ColumnarFormat::from_path(path).ok_or_else(|| {
+        anyhow::anyhow!("{}: not a .parquet, .pq, .arrow, .ipc or .feather file", path.display())
+    })?;
+    let fields: Vec<Field> = columns
+        .iter()
+        .map(|(name, column)| {
+            Field::new(*name, column.data_type().clone(), column.null_count() > 0)
+        })
+        .collect();
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns.into_iter().map(|(_, column)| column).collect(),
+    )?;
+    let file = File::create(path).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+    match format {
+        ColumnarFormat::Parquet => {
+            let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+            let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(props))?;
+            writer.write(&batch)?;
+            writer.close()?;
+        }
+        ColumnarFormat::ArrowIpc => {
+            let mut writer = FileWriter::try_new(file, &batch.schema())?;
+            writer.write(&batch)?;
+            writer.finish()?;
+        }
+    }
+    Ok(())
+}
+
+fn read_batches(path: &Path) -> anyhow::Result<Vec<RecordBatch>> {
+    let format = ColumnarFormat::from_path(path).ok_or_else(|| {
+        anyhow::anyhow!("{}: not a .parquet, .pq, .arrow, .ipc or .feather file", path.display())
+    })?;
+    let file = File::open(path).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+    let batches = match format {
+        ColumnarFormat::Parquet => {
+            ParquetRecordBatchReaderBuilder::try_new(file)?.build()?.collect::<Result<_, _>>()?
+        }
+        ColumnarFormat::ArrowIpc => FileReader::try_new(file, None)?.collect::<Result<_, _>>()?,
+    };
+    Ok(batches)
+}
+
+/// Typed access to a batch's columns, casting whatever width or encoding a file uses.
+struct Columns<'a> {
+    batch: &'a RecordBatch,
+    path: &'a Path,
+}
+
+impl Columns<'_> {
+    fn column(&self, name: &str, to: &DataType) -> anyhow::Result<ArrayRef> {
+        let column = self
+            .batch
+            .column_by_name(name)
+            .ok_or_else(|| anyhow::anyhow!("{}: no `{name}` column", self.path.display()))?;
+        let column = cast(column, to)
+            .map_err(|e| anyhow::anyhow!("{}: column `{name}`: {e}", self.path.display()))?;
+        Ok(column)
+    }
+
+    fn required(&self, name: &str, to: &DataType) -> anyhow::Result<ArrayRef> {
+        let column = self.column(name, to)?;
+        if column.null_count() > 0 {
+            anyhow::bail!("{}: column `{name}` has missing values", self.path.display());
+        }
+        Ok(column)
+    }
+
+    fn floats(&self, name: &str) -> anyhow::Result<Vec<f64>> {
+        let column = self.required(name, &DataType::Float64)?;
+        Ok(column.as_primitive::<Float64Type>().values().to_vec())
+    }
+
+    fn integers(&self, name: &str) -> anyhow::Result<Vec<i64>> {
+        let column = self.required(name, &DataType::Int64)?;
+        Ok(column.as_primitive::<Int64Type>().values().to_vec())
+    }
+
+    fn timestamps(&self, name: &str) -> anyhow::Result<Vec<DateTime<Utc>>> {
+        // Timestamps without a zone are taken as UTC
+        let to = DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into()));
+        let column = self.required(name, &to)?;
+        let column = column.as_primitive::<TimestampNanosecondType>();
+        Ok(column.values().iter().map(|n| DateTime::from_timestamp_nanos(*n)).collect())
+    }
+
+    fn strings(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        let column = self.required(name, &DataType::Utf8)?;
+        Ok(column.as_string::<i32>().iter().map(|s| s.unwrap_or_default().to_string()).collect())
+    }
+
+    fn optional_strings(&self, name: &str) -> anyhow::Result<Option<Vec<Option<String>>>> {
+        if self.batch.column_by_name(name).is_none() {
+            return Ok(None);
+        }
+        let column = self.column(name, &DataType::Utf8)?;
+        Ok(Some(column.as_string::<i32>().iter().map(|s| s.map(str::to_string)).collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::config;
+    use crate::engine::{Engine, EngineEvent};
+    use arrow::array::{Int32Array, TimestampMillisecondArray};
+
+    const FORMATS: [&str; 2] = ["parquet", "arrow"];
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("nexow-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    fn bar(minute: i64, symbol: &str, close: f64) -> Bar {
+        let start: DateTime<Utc> = "2024-01-02T00:00:00Z".parse().unwrap();
+        Bar {
+            ts: start + chrono::Duration::minutes(minute),
+            open: close - 0.5,
+            high: close + 1.0,
+            low: close - 1.0,
+            close,
+            volume: 250.0,
+            symbol: symbol.to_string(),
+            regime: (minute % 2 == 0).then(|| "calm".to_string()),
+        }
+    }
+
+    fn report() -> RunReport {
+        let config = config(serde_json::json!({
+            "bracket": { "take_profit_pct": 0.01, "stop_loss_pct": 0.01 },
+            "allow_short": true,
+        }));
+        let summary = Engine::spawn(config).rx_evt.iter().find_map(|evt| match evt {
+            EngineEvent::Summary(report) => Some(*report),
+            _ => None,
+        });
+        summary.unwrap()
+    }
+
+    #[test]
+    fn bars_round_trip() {
+        let dir = temp_dir("columnar-bars");
+        let bars: Vec<_> =
+            (0..5).flat_map(|m| [bar(m, "AAA", 100.0), bar(m, "BBB", 50.0)]).collect();
+        for format in FORMATS {
+            let path = dir.join(format!("bars.{format}"));
+            write_bars(&path, &bars).unwrap();
+            assert_eq!(json(&read_bars(&path).unwrap()), json(&bars), "{format}");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn exported_reports_read_back_unchanged() {
+        let dir = temp_dir("columnar-report");
+        let report = report();
+        assert!(!report.trades.is_empty());
+        for format in [ColumnarFormat::Parquet, ColumnarFormat::ArrowIpc] {
+            report.export(&dir, format).unwrap();
+            let file = |name: &str| dir.join(format!("{name}.{}", format.extension()));
+            assert_eq!(json(&read_trades(file("trades")).unwrap()), json(&report.trades));
+            let curve = read_equity_curve(file("equity_curve")).unwrap();
+            assert_eq!(json(&curve), json(&report.equity_curve));
+            assert_eq!(json(&read_metrics(file("metrics")).unwrap()), json(&report.metrics));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn metrics_accept_counts_of_any_integer_type() {
+        let dir = temp_dir("columnar-metrics");
+        let metrics = report().metrics;
+        let serde_json::Value::Object(fields) = json(&metrics) else { unreachable!() };
+        // As pandas writes them: signed counts next to the floats
+        let columns: Vec<(&str, ArrayRef)> = fields
+            .iter()
+            .map(|(name, value)| {
+                let column: ArrayRef = match value.as_u64() {
+                    Some(n) => Arc::new(Int32Array::from(vec![n as i32])),
+                    None => Arc::new(Float64Array::from(vec![value.as_f64()])),
+                };
+                (name.as_str(), column)
+            })
+            .collect();
+        for format in FORMATS {
+            let path = dir.join(format!("metrics.{format}"));
+            write_batch(&path, columns.clone()).unwrap();
+            assert_eq!(json(&read_metrics(&path).unwrap()), json(&metrics), "{format}");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn naive_timestamps_are_utc_and_a_single_symbol_needs_no_column() {
+        let dir = temp_dir("columnar-naive");
+        let bars: Vec<_> = (0..3).map(|m| bar(m, "AAA", 100.0)).collect();
+        let millis = bars.iter().map(|b| b.ts.timestamp_millis());
+        let columns: Vec<(&str, ArrayRef)> = vec![
+            ("ts", Arc::new(TimestampMillisecondArray::from_iter_values(millis))),
+            ("open", floats(bars.iter().map(|b| b.open))),
+            ("high", floats(bars.iter().map(|b| b.high))),
+            ("low", floats(bars.iter().map(|b| b.low))),
+            ("close", floats(bars.iter().map(|b| b.close))),
+            ("volume", floats(bars.iter().map(|b| b.volume))),
+        ];
+        for format in FORMATS {
+            let path = dir.join(format!("aaa.{format}"));
+            write_batch(&path, columns.clone()).unwrap();
+            let mut source = ColumnarSource::new(vec![path.to_string_lossy().into_owned()]);
+            let loaded = source.load(&["AAA".to_string()]).unwrap();
+            let expected: Vec<_> =
+                bars.iter().cloned().map(|b| Bar { regime: None, ..b }).collect();
+            assert_eq!(json(&loaded), json(&[expected]), "{format}");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bars_are_checked_like_csv_rows() {
+        let dir = temp_dir("columnar-invalid");
+        type Break = fn(&mut Bar);
+        let broken: [(&str, Break); 5] = [
+            ("nan close", |b| b.close = f64::NAN),
+            ("zero low", |b| b.low = 0.0),
+            ("negative volume", |b| b.volume = -1.0),
+            ("close above high", |b| b.close = b.high + 1.0),
+            ("open below low", |b| b.open = b.low - 0.5),
+        ];
+        for (what, break_bar) in broken {
+            let mut bars: Vec<_> = (0..3).map(|m| bar(m, "AAA", 100.0)).collect();
+            break_bar(&mut bars[1]);
+            for format in FORMATS {
+                let path = dir.join(format!("bars.{format}"));
+                write_bars(&path, &bars).unwrap();
+                let err = read_bars(&path).unwrap_err().to_string();
+                assert!(err.contains("row 2"), "{what} in {format}: {err}");
+                let mut source = ColumnarSource::new(vec![path.to_string_lossy().into_owned()]);
+                assert!(source.load(&["AAA".to_string()]).is_err(), "{what} in {format}");
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,364 @@
+use crate::data::cholesky;
+use crate::error::{ConfigError, FieldError};
+use crate::types::*;
+
+impl EngineConfig {
+    /// Checks every field and reports all problems at once, each under its field path
+    /// (`bracket.stop_loss_pct`, `assets[1].tick_size`, ...).
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut v = Validator::default();
+
+        for (i, symbol) in self.symbols.iter().enumerate() {
+            if symbol.trim().is_empty() {
+                v.fail(format!("symbols[{i}]"), "must not be empty");
+            } else if self.symbols[..i].contains(symbol) {
+                v.fail(format!("symbols[{i}]"), format!("duplicate symbol {symbol}"));
+            }
+        }
+        if self.bar_interval_ms == 0 {
+            v.fail("bar_interval_ms", "must be at least 1");
+        }
+        if self.length_bars == 0 {
+            v.fail("length_bars", "must be at least 1");
+        }
+        if self.rf_trees == 0 {
+            v.fail("rf_trees", "must be at least 1");
+        }
+        if self.rf_max_depth == 0 {
+            v.fail("rf_max_depth", "must be at least 1");
+        }
+        // The test split must keep at least one bar to trade on
+        if !(0.0..1.0).contains(&self.train_split) {
+            v.fail("train_split", "must be in [0, 1)");
+        }
+        v.positive("starting_cash", self.starting_cash);
+
+        if let Some(bracket) = &self.bracket {
+            v.positive("bracket.take_profit_pct", bracket.take_profit_pct);
+            v.positive("bracket.stop_loss_pct", bracket.stop_loss_pct);
+            // A long's stop below zero could never trigger
+            if bracket.stop_loss_pct >= 1.0 {
+                v.fail("bracket.stop_loss_pct", "must be below 1");
+            }
+        }
+
+        // Offsets of 1 or more would put a sell stop or a buy limit at or below zero
+        let mut offset = |field: &str, pct: f64| {
+            v.non_negative(format!("entry.order.{field}"), pct);
+            if pct >= 1.0 {
+                v.fail(format!("entry.order.{field}"), "must be below 1");
+            }
+        };
+        match self.entry.order {
+            EntryOrder::Market => {}
+            EntryOrder::Limit { offset_pct } | EntryOrder::Stop { offset_pct } => {
+                offset("offset_pct", offset_pct)
+            }
+            EntryOrder::StopLimit { stop_pct, limit_pct } => {
+                offset("stop_pct", stop_pct);
+                offset("limit_pct", limit_pct);
+            }
+        }
+
+        match &self.commission {
+            CommissionModel::None => {}
+            CommissionModel::Fixed { per_trade } => {
+                v.non_negative("commission.per_trade", *per_trade)
+            }
+            CommissionModel::PerShare { rate, min } => {
+                v.non_negative("commission.rate", *rate);
+                v.non_negative("commission.min", *min);
+            }
+            CommissionModel::Bps { bps } => v.non_negative("commission.bps", *bps),
+            CommissionModel::Tiered { tiers } => {
+                if tiers.is_empty() {
+                    v.fail("commission.tiers", "must have at least one tier");
+                }
+                for (i, tier) in tiers.iter().enumerate() {
+                    v.non_negative(format!("commission.tiers[{i}].min_volume"), tier.min_volume);
+                    // Negative maker fees are rebates
+                    v.finite(format!("commission.tiers[{i}].maker_bps"), tier.maker_bps);
+                    v.non_negative(format!("commission.tiers[{i}].taker_bps"), tier.taker_bps);
+                }
+            }
+        }
+
+        match &self.slippage {
+            SlippageModel::None => {}
+            SlippageModel::FixedTicks { ticks } => v.non_negative("slippage.ticks", *ticks),
+            SlippageModel::RangeFraction { fraction } => {
+                v.non_negative("slippage.fraction", *fraction)
+            }
+            SlippageModel::SqrtImpact { coefficient } => {
+                v.non_negative("slippage.coefficient", *coefficient)
+            }
+        }
+
+        if let Some(fraction) = self.max_volume_participation {
+            if !(fraction > 0.0 && fraction <= 1.0) {
+                v.fail("max_volume_participation", "must be in (0, 1]");
+            }
+        }
+
+        for (i, asset) in self.assets.iter().enumerate() {
+            if asset.symbol.trim().is_empty() {
+                v.fail(format!("assets[{i}].symbol"), "must not be empty");
+            }
+            v.non_negative(format!("assets[{i}].lot_size"), asset.lot_size);
+            v.positive(format!("assets[{i}].tick_size"), asset.tick_size);
+        }
+
+        v.non_negative("margin.initial", self.margin.initial);
+        v.non_negative("margin.maintenance", self.margin.maintenance);
+        // Otherwise a freshly opened position would already be under a margin call
+        if self.margin.maintenance > self.margin.initial {
+            v.fail("margin.maintenance", "must not exceed margin.initial");
+        }
+
+        match &self.sizing {
+            Sizing::FixedFractional { fraction } => v.positive("sizing.fraction", *fraction),
+            Sizing::FixedNotional { notional } => v.positive("sizing.notional", *notional),
+            Sizing::FixedUnits { units } => v.positive("sizing.units", *units),
+            Sizing::PercentRisk { risk_fraction } => {
+                v.positive("sizing.risk_fraction", *risk_fraction);
+                if *risk_fraction > 1.0 {
+                    v.fail("sizing.risk_fraction", "must be at most 1");
+                }
+            }
+        }
+
+        match self.price_model {
+            PriceModel::Uniform { vol } => v.positive("price_model.vol", vol),
+            PriceModel::Gbm { drift, volatility } => {
+                v.finite("price_model.drift", drift);
+                v.non_negative("price_model.volatility", volatility);
+            }
+            PriceModel::JumpDiffusion {
+                drift,
+                volatility,
+                jump_intensity,
+                jump_mean,
+                jump_std,
+            } => {
+                v.finite("price_model.drift", drift);
+                v.non_negative("price_model.volatility", volatility);
+                v.non_negative("price_model.jump_intensity", jump_intensity);
+                v.finite("price_model.jump_mean", jump_mean);
+                v.non_negative("price_model.jump_std", jump_std);
+            }
+            PriceModel::Garch { omega, alpha, beta } => {
+                v.positive("price_model.omega", omega);
+                v.non_negative("price_model.alpha", alpha);
+                v.non_negative("price_model.beta", beta);
+                // Otherwise the variance has no long-run level and explodes
+                if alpha + beta >= 1.0 {
+                    v.fail("price_model.beta", "alpha + beta must be below 1");
+                }
+            }
+            PriceModel::RegimeSwitching { ref regimes, ref transitions } => {
+                if regimes.is_empty() {
+                    v.fail("price_model.regimes", "must not be empty");
+                }
+                for (i, regime) in regimes.iter().enumerate() {
+                    if regime.name.trim().is_empty() {
+                        v.fail(format!("price_model.regimes[{i}].name"), "must not be empty");
+                    }
+                    v.finite(format!("price_model.regimes[{i}].drift"), regime.drift);
+                    v.non_negative(
+                        format!("price_model.regimes[{i}].volatility"),
+                        regime.volatility,
+                    );
+                }
+                let n = regimes.len();
+                if transitions.len() != n || transitions.iter().any(|row| row.len() != n) {
+                    v.fail(
+                        "price_model.transitions",
+                        format!("must be {n}x{n}, one row per regime"),
+                    );
+                }
+                for (i, row) in transitions.iter().enumerate() {
+                    if row.iter().any(|p| !(0.0..=1.0).contains(p)) {
+                        v.fail(format!("price_model.transitions[{i}]"), "must hold probabilities");
+                    } else if (row.iter().sum::<f64>() - 1.0).abs() > 1e-9 {
+                        v.fail(format!("price_model.transitions[{i}]"), "must sum to 1");
+                    }
+                }
+            }
+        }
+
+        if let Some(dependence) = &self.dependence {
+            // Runs without symbols trade a single default one
+            let n = self.symbols.len().max(1);
+            let (matrix, correlation) = match dependence {
+                Dependence::Correlation { matrix } => (matrix, true),
+                Dependence::Covariance { matrix } => (matrix, false),
+            };
+            match self.price_model {
+                PriceModel::Uniform { .. } => {
+                    v.fail("dependence", "needs a price model driven by normal shocks")
+                }
+                PriceModel::Garch { .. } | PriceModel::RegimeSwitching { .. } if !correlation => {
+                    v.fail("dependence", "a covariance needs a Gbm or JumpDiffusion price model")
+                }
+                _ => {}
+            }
+            if matrix.len() != n || matrix.iter().any(|row| row.len() != n) {
+                v.fail("dependence.matrix", format!("must be {n}x{n}, one row per symbol"));
+            } else {
+                let before = v.errors.len();
+                for (i, row) in matrix.iter().enumerate() {
+                    for (j, &value) in row.iter().enumerate() {
+                        let field = format!("dependence.matrix[{i}][{j}]");
+                        if i == j && correlation && value != 1.0 {
+                            v.fail(field, "must be 1 on the diagonal");
+                        } else if i == j {
+                            v.positive(field, value);
+                        } else if correlation && !(-1.0..=1.0).contains(&value) {
+                            v.fail(field, "must be between -1 and 1");
+                        } else {
+                            v.finite(field, value);
+                        }
+                    }
+                }
+                if v.errors.len() == before {
+                    if let Err(e) = cholesky(matrix) {
+                        v.fail("dependence.matrix", e.to_string());
+                    }
+                }
+            }
+        }
+
+        if let BarSource::Csv(csv) = &self.data_source {
+            if self.symbols.is_empty() {
+                v.fail("symbols", "must list the symbols to load from CSV");
+            }
+            if csv.paths.is_empty() {
+                v.fail("data_source.paths", "must not be empty");
+            }
+            for (i, path) in csv.paths.iter().enumerate() {
+                if path.trim().is_empty() {
+                    v.fail(format!("data_source.paths[{i}]"), "must not be empty");
+                }
+            }
+            let columns = &csv.columns;
+            for (name, column) in [
+                ("timestamp", &columns.timestamp),
+                ("open", &columns.open),
+                ("high", &columns.high),
+                ("low", &columns.low),
+                ("close", &columns.close),
+                ("volume", &columns.volume),
+            ] {
+                if column.trim().is_empty() {
+                    v.fail(format!("data_source.columns.{name}"), "must not be empty");
+                }
+            }
+            if let Some(timezone) = &csv.timezone {
+                if timezone.parse::<chrono_tz::Tz>().is_err() {
+                    v.fail("data_source.timezone", format!("unknown timezone {timezone}"));
+                }
+            }
+            if !csv.delimiter.is_ascii() {
+                v.fail("data_source.delimiter", "must be a single-byte character");
+            }
+        }
+
+        if let BarSource::Columnar { paths } = &self.data_source {
+            if !cfg!(feature = "columnar") {
+                v.fail("data_source", "needs the engine built with the `columnar` feature");
+            }
+            if self.symbols.is_empty() {
+                v.fail("symbols", "must list the symbols to load from files");
+            }
+            if paths.is_empty() {
+                v.fail("data_source.paths", "must not be empty");
+            }
+            for (i, path) in paths.iter().enumerate() {
+                if ColumnarFormat::from_path(path).is_none() {
+                    v.fail(
+                        format!("data_source.paths[{i}]"),
+                        "must end in .parquet, .pq, .arrow, .ipc or .feather",
+                    );
+                }
+            }
+        }
+        if let Some(export) = &self.export {
+            if !cfg!(feature = "columnar") {
+                v.fail("export", "needs the engine built with the `columnar` feature");
+            }
+            if export.dir.trim().is_empty() {
+                v.fail("export.dir", "must not be empty");
+            }
+        }
+
+        if self.event_buffer == Some(0) {
+            v.fail("event_buffer", "must be at least 1");
+        }
+        if self.checkpoint_every == Some(0) {
+            v.fail("checkpoint_every", "must be at least 1");
+        }
+
+        v.finish()
+    }
+}
+
+#[derive(Default)]
+struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    fn fail(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(FieldError { field: field.into(), message: message.into() });
+    }
+
+    fn finite(&mut self, field: impl Into<String>, value: f64) {
+        if !value.is_finite() {
+            self.fail(field, "must be a finite number");
+        }
+    }
+
+    fn positive(&mut self, field: impl Into<String>, value: f64) {
+        if !(value.is_finite() && value > 0.0) {
+            self.fail(field, "must be greater than 0");
+        }
+    }
+
+    fn non_negative(&mut self, field: impl Into<String>, value: f64) {
+        if !(value.is_finite() && value >= 0.0) {
+            self.fail(field, "must be 0 or greater");
+        }
+    }
+
+    fn finish(self) -> Result<(), ConfigError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { errors: self.errors })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::tests::config;
+
+    #[test]
+    fn maintenance_margin_above_initial_is_rejected() {
+        let config =
+            config(serde_json::json!({ "margin": { "initial": 0.2, "maintenance": 0.3 } }));
+        let errors = config.validate().unwrap_err().errors;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "margin.maintenance");
+    }
+
+    #[test]
+    fn entry_offsets_must_stay_below_one() {
+        let order = serde_json::json!({ "StopLimit": { "stop_pct": 0.01, "limit_pct": 1.0 } });
+        let config = config(serde_json::json!({ "entry": { "order": order } }));
+        let errors = config.validate().unwrap_err().errors;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "entry.order.limit_pct");
+    }
+
+}
@@ -1,46 +1,688 @@
+use crate::metrics::MS_PER_YEAR;
 use crate::types::*;
-use rand::Rng;
-use chrono::{Utc, Duration};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use rand_distr::{Distribution, Poisson, StandardNormal};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
 
-pub fn generate_synthetic_bars(
+/// Supplies a run's bars: one time-ordered series per symbol. The engine trains on the
+/// start of each series and trades the rest. Resuming from a checkpoint loads again, so
+/// a source must return the same bars every time.
+pub trait DataSource: Send {
+    fn load(&mut self, symbols: &[String]) -> anyhow::Result<Vec<Vec<Bar>>>;
+}
+
+/// Default source: a series per symbol on a shared time grid, drawn from one seeded
+/// random number generator. Series are independent unless a `dependence` is set.
+pub struct SyntheticSource {
+    pub seed: u64,
+    pub start: DateTime<Utc>,
+    pub start_price: f64,
+    pub interval_ms: u64,
+    pub length: usize,
+    pub model: PriceModel,
+    pub dependence: Option<Dependence>,
+}
+
+impl SyntheticSource {
+    pub fn new(seed: u64, start: DateTime<Utc>, interval_ms: u64, length: usize) -> Self {
+        Self {
+            seed,
+            start,
+            start_price: 100.0,
+            interval_ms,
+            length,
+            model: PriceModel::default(),
+            dependence: None,
+        }
+    }
+
+    pub fn with_model(mut self, model: PriceModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Correlates the symbols' returns; the matrix follows the order of the loaded symbols.
+    pub fn with_dependence(mut self, dependence: Option<Dependence>) -> Self {
+        self.dependence = dependence;
+        self
+    }
+}
+
+impl DataSource for SyntheticSource {
+    fn load(&mut self, symbols: &[String]) -> anyhow::Result<Vec<Vec<Bar>>> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        if let Some(dependence) = &self.dependence {
+            let (correlation, vols) = dependence.split();
+            let mut generators = Vec::with_capacity(symbols.len());
+            for i in 0..symbols.len() {
+                let model = match &vols {
+                    Some(vols) => self.model.with_volatility(vols[i])?,
+                    None => self.model.clone(),
+                };
+                generators.push(model.generator(self.interval_ms));
+            }
+            return generate_correlated_bars(
+                &mut generators,
+                &cholesky(&correlation)?,
+                &mut rng,
+                symbols,
+                self.start,
+                self.start_price,
+                self.interval_ms,
+                self.length,
+            );
+        }
+        Ok(symbols
+            .iter()
+            .map(|symbol| {
+                generate_bars(
+                    self.model.generator(self.interval_ms).as_mut(),
+                    &mut rng,
+                    symbol,
+                    self.start,
+                    self.start_price,
+                    self.interval_ms,
+                    self.length,
+                )
+            })
+            .collect())
+    }
+}
+
+/// Bars with uniform returns in `-vol..vol`; shorthand for [`generate_bars`] with [`Uniform`].
+pub fn generate_synthetic_bars<R: Rng>(
+    rng: &mut R,
     symbol: &str,
+    start: DateTime<Utc>,
     start_price: f64,
     interval_ms: u64,
     n: usize,
     vol: f64,
 ) -> Vec<Bar> {
-    let mut rng = rand::thread_rng();
+    generate_bars(&mut Uniform { vol }, rng, symbol, start, start_price, interval_ms, n)
+}
+
+/// Draws `n` consecutive bars of `symbol` from `generator`, each opening at the previous
+/// close.
+pub fn generate_bars<R: Rng>(
+    generator: &mut dyn BarGenerator,
+    rng: &mut R,
+    symbol: &str,
+    start: DateTime<Utc>,
+    start_price: f64,
+    interval_ms: u64,
+    n: usize,
+) -> Vec<Bar> {
     let mut price = start_price;
-    let mut ts = Utc::now();
+    let mut ts = start;
     let mut bars = Vec::with_capacity(n);
 
     for _ in 0..n {
-        let ret = rng.gen_range(-vol..vol);
         let open = price;
-        price = (price * (1.0 + ret)).max(0.0001);
-        let close = price;
-        let high = open.max(close) * (1.0 + rng.gen_range(0.0..vol.abs()));
-        let low = open.min(close) * (1.0 - rng.gen_range(0.0..vol.abs()));
+        let Candle { high, low, close } = generator.next_bar(rng, open);
         let volume = rng.gen_range(1000.0..10000.0);
+        let regime = generator.regime().map(str::to_string);
 
-        bars.push(Bar {
-            ts,
-            open,
-            high,
-            low,
-            close,
-            volume,
-            symbol: symbol.to_string(),
-        });
+        bars.push(Bar { ts, open, high, low, close, volume, symbol: symbol.to_string(), regime });
 
+        price = close;
         ts += Duration::milliseconds(interval_ms as i64);
     }
 
     bars
 }
 
+/// Draws `n` bars for every symbol at once, with the diffusion shocks of all markets
+/// correlated through `cholesky`, the lower-triangular factor of their correlation matrix.
+/// Fails for generators that are not driven by normal shocks.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_correlated_bars<R: Rng>(
+    generators: &mut [Box<dyn BarGenerator>],
+    cholesky: &[Vec<f64>],
+    rng: &mut R,
+    symbols: &[String],
+    start: DateTime<Utc>,
+    start_price: f64,
+    interval_ms: u64,
+    n: usize,
+) -> anyhow::Result<Vec<Vec<Bar>>> {
+    let m = symbols.len();
+    anyhow::ensure!(
+        generators.len() == m && cholesky.len() == m,
+        "expected {m} generators and a {m}x{m} factor"
+    );
+    let mut prices = vec![start_price; m];
+    let mut series: Vec<Vec<Bar>> = (0..m).map(|_| Vec::with_capacity(n)).collect();
+    let mut ts = start;
+
+    for _ in 0..n {
+        let independent: Vec<Shocks> = (0..m).map(|_| draw_shocks(rng)).collect();
+        for i in 0..m {
+            let shocks: Shocks =
+                std::array::from_fn(|k| (0..=i).map(|j| cholesky[i][j] * independent[j][k]).sum());
+            let open = prices[i];
+            let Candle { high, low, close } = generators[i]
+                .next_bar_with(rng, open, &shocks)
+                .ok_or_else(|| anyhow::anyhow!("price model cannot be correlated"))?;
+            let volume = rng.gen_range(1000.0..10000.0);
+            let regime = generators[i].regime().map(str::to_string);
+            series[i].push(Bar {
+                ts,
+                open,
+                high,
+                low,
+                close,
+                volume,
+                symbol: symbols[i].clone(),
+                regime,
+            });
+            prices[i] = close;
+        }
+        ts += Duration::milliseconds(interval_ms as i64);
+    }
+
+    Ok(series)
+}
+
+/// Lower-triangular `L` with `L * L^T = matrix`. Positive semi-definite matrices are
+/// accepted, so perfectly correlated markets work; anything else is an error.
+pub fn cholesky(matrix: &[Vec<f64>]) -> anyhow::Result<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    anyhow::ensure!(matrix.iter().all(|row| row.len() == n), "matrix must be square");
+    let tolerance = 1e-10;
+    let mut l = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            anyhow::ensure!(
+                (matrix[i][j] - matrix[j][i]).abs() <= tolerance,
+                "matrix must be symmetric"
+            );
+            let partial: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            if i == j {
+                let pivot = matrix[i][i] - partial;
+                anyhow::ensure!(pivot >= -tolerance, "matrix must be positive semi-definite");
+                l[i][i] = pivot.max(0.0).sqrt();
+            } else if l[j][j] > 0.0 {
+                l[i][j] = (matrix[i][j] - partial) / l[j][j];
+            } else {
+                // A degenerate column must not be needed by later rows either
+                anyhow::ensure!(
+                    (matrix[i][j] - partial).abs() <= tolerance,
+                    "matrix must be positive semi-definite"
+                );
+            }
+        }
+    }
+    Ok(l)
+}
+
+impl Dependence {
+    /// Correlation matrix, plus the volatilities on the diagonal of a covariance matrix.
+    pub fn split(&self) -> (Vec<Vec<f64>>, Option<Vec<f64>>) {
+        match self {
+            Dependence::Correlation { matrix } => (matrix.clone(), None),
+            Dependence::Covariance { matrix } => {
+                let vols: Vec<f64> = (0..matrix.len())
+                    .map(|i| matrix[i].get(i).copied().unwrap_or(0.0).max(0.0).sqrt())
+                    .collect();
+                let correlation = matrix
+                    .iter()
+                    .enumerate()
+                    .map(|(i, row)| {
+                        row.iter()
+                            .enumerate()
+                            .map(|(j, c)| {
+                                let scale = vols[i] * vols.get(j).copied().unwrap_or(0.0);
+                                if scale > 0.0 {
+                                    c / scale
+                                } else if i == j {
+                                    1.0
+                                } else {
+                                    0.0
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect();
+                (correlation, Some(vols))
+            }
+        }
+    }
+}
+
+/// Sub-steps simulated within a bar by the path-based generators.
+pub const SUBSTEPS: usize = 16;
+
+/// Standard normal shocks driving one bar's diffusion, one per sub-step.
+pub type Shocks = [f64; SUBSTEPS];
+
+/// Range and close of one bar, as drawn by a `BarGenerator`.
+#[derive(Clone, Copy, Debug)]
+pub struct Candle {
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+/// Draws synthetic bars one after another; a generator may carry state from bar to bar.
+pub trait BarGenerator: Send {
+    fn next_bar(&mut self, rng: &mut dyn RngCore, open: f64) -> Candle;
+
+    /// Draws the next bar with its diffusion driven by `shocks`, which lets a caller
+    /// correlate several markets. `None` for generators that have no diffusion.
+    fn next_bar_with(
+        &mut self,
+        _rng: &mut dyn RngCore,
+        _open: f64,
+        _shocks: &Shocks,
+    ) -> Option<Candle> {
+        None
+    }
+
+    /// Name of the hidden regime the last bar was drawn in, for generators that have one.
+    fn regime(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Uniform returns in `-vol..vol`, with wicks drawn independently of the return.
+pub struct Uniform {
+    pub vol: f64,
+}
+
+impl BarGenerator for Uniform {
+    fn next_bar(&mut self, rng: &mut dyn RngCore, open: f64) -> Candle {
+        let ret = rng.gen_range(-self.vol..self.vol);
+        let close = (open * (1.0 + ret)).max(0.0001);
+        let high = open.max(close) * (1.0 + rng.gen_range(0.0..self.vol.abs()));
+        let low = open.min(close) * (1.0 - rng.gen_range(0.0..self.vol.abs()));
+        Candle { high, low, close }
+    }
+}
+
+/// Geometric Brownian motion with annualized `drift` and `volatility`.
+pub struct Gbm {
+    pub drift: f64,
+    pub volatility: f64,
+    /// Bar length in years.
+    dt: f64,
+}
+
+impl Gbm {
+    pub fn new(drift: f64, volatility: f64, interval_ms: u64) -> Self {
+        Self { drift, volatility, dt: years(interval_ms) }
+    }
+
+    fn bar(&self, open: f64, shocks: &Shocks) -> Candle {
+        let h = self.dt / SUBSTEPS as f64;
+        let mean = (self.drift - 0.5 * self.volatility.powi(2)) * h;
+        let sd = self.volatility * h.sqrt();
+        trace(open, shocks.iter().map(|z| mean + sd * z))
+    }
+}
+
+impl BarGenerator for Gbm {
+    fn next_bar(&mut self, rng: &mut dyn RngCore, open: f64) -> Candle {
+        self.bar(open, &draw_shocks(rng))
+    }
+
+    fn next_bar_with(
+        &mut self,
+        _rng: &mut dyn RngCore,
+        open: f64,
+        shocks: &Shocks,
+    ) -> Option<Candle> {
+        Some(self.bar(open, shocks))
+    }
+}
+
+/// Merton jump-diffusion: GBM plus Poisson jumps whose log sizes are normal. The drift is
+/// compensated so that jumps leave the expected return at `drift`.
+pub struct JumpDiffusion {
+    pub drift: f64,
+    pub volatility: f64,
+    pub jump_mean: f64,
+    pub jump_std: f64,
+    /// Jumps per year.
+    pub jump_intensity: f64,
+    dt: f64,
+    jumps: Option<Poisson<f64>>,
+}
+
+impl JumpDiffusion {
+    pub fn new(
+        drift: f64,
+        volatility: f64,
+        jump_intensity: f64,
+        jump_mean: f64,
+        jump_std: f64,
+        interval_ms: u64,
+    ) -> Self {
+        let dt = years(interval_ms);
+        let jumps = Poisson::new(jump_intensity * dt / SUBSTEPS as f64).ok();
+        Self { drift, volatility, jump_mean, jump_std, jump_intensity, dt, jumps }
+    }
+
+    /// Jumps are drawn from `rng` and stay independent across markets.
+    fn bar(&self, rng: &mut dyn RngCore, open: f64, shocks: &Shocks) -> Candle {
+        let h = self.dt / SUBSTEPS as f64;
+        let kappa = (self.jump_mean + 0.5 * self.jump_std.powi(2)).exp() - 1.0;
+        let mean = (self.drift - 0.5 * self.volatility.powi(2) - self.jump_intensity * kappa) * h;
+        let sd = self.volatility * h.sqrt();
+        let jumps = self.jumps;
+        let (jump_mean, jump_std) = (self.jump_mean, self.jump_std);
+        trace(
+            open,
+            shocks.iter().map(|z| {
+                let diffusion = mean + sd * z;
+                let n = jumps.map(|p| p.sample(rng)).unwrap_or(0.0);
+                if n > 0.0 {
+                    diffusion + n * jump_mean + n.sqrt() * jump_std * normal(rng)
+                } else {
+                    diffusion
+                }
+            }),
+        )
+    }
+}
+
+impl BarGenerator for JumpDiffusion {
+    fn next_bar(&mut self, rng: &mut dyn RngCore, open: f64) -> Candle {
+        let shocks = draw_shocks(rng);
+        self.bar(rng, open, &shocks)
+    }
+
+    fn next_bar_with(
+        &mut self,
+        rng: &mut dyn RngCore,
+        open: f64,
+        shocks: &Shocks,
+    ) -> Option<Candle> {
+        Some(self.bar(rng, open, shocks))
+    }
+}
+
+/// GARCH(1,1) log returns with zero mean. The variance starts at its long-run level
+/// `omega / (1 - alpha - beta)` and is updated from each bar's return.
+pub struct Garch {
+    pub omega: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    variance: f64,
+}
+
+impl Garch {
+    pub fn new(omega: f64, alpha: f64, beta: f64) -> Self {
+        let persistence = alpha + beta;
+        let variance = if persistence < 1.0 { omega / (1.0 - persistence) } else { omega };
+        Self { omega, alpha, beta, variance }
+    }
+
+    fn bar(&mut self, open: f64, shocks: &Shocks) -> Candle {
+        // The bar's return is spread evenly over the sub-steps of its path
+        let sd = (self.variance / SUBSTEPS as f64).sqrt();
+        let mut ret = 0.0;
+        let candle = trace(
+            open,
+            shocks.iter().map(|z| {
+                let r = sd * z;
+                ret += r;
+                r
+            }),
+        );
+        self.variance = self.omega + self.alpha * ret * ret + self.beta * self.variance;
+        candle
+    }
+}
+
+impl BarGenerator for Garch {
+    fn next_bar(&mut self, rng: &mut dyn RngCore, open: f64) -> Candle {
+        self.bar(open, &draw_shocks(rng))
+    }
+
+    fn next_bar_with(
+        &mut self,
+        _rng: &mut dyn RngCore,
+        open: f64,
+        shocks: &Shocks,
+    ) -> Option<Candle> {
+        Some(self.bar(open, shocks))
+    }
+}
+
+/// Markov regime switching: before each bar the regime moves along its row of
+/// `transitions`, then the bar is drawn by that regime's GBM.
+pub struct RegimeSwitching {
+    names: Vec<String>,
+    regimes: Vec<Gbm>,
+    transitions: Vec<Vec<f64>>,
+    current: usize,
+}
+
+impl RegimeSwitching {
+    pub fn new(regimes: &[Regime], transitions: Vec<Vec<f64>>, interval_ms: u64) -> Self {
+        Self {
+            names: regimes.iter().map(|r| r.name.clone()).collect(),
+            regimes: regimes.iter().map(|r| Gbm::new(r.drift, r.volatility, interval_ms)).collect(),
+            transitions,
+            current: 0,
+        }
+    }
+
+    fn switch(&mut self, rng: &mut dyn RngCore) {
+        let row = &self.transitions[self.current];
+        let mut u: f64 = rng.gen();
+        // Rounding may leave `u` above the row's total; stay put then
+        for (next, p) in row.iter().enumerate() {
+            if u < *p {
+                self.current = next;
+                return;
+            }
+            u -= p;
+        }
+    }
+}
+
+impl BarGenerator for RegimeSwitching {
+    fn next_bar(&mut self, rng: &mut dyn RngCore, open: f64) -> Candle {
+        self.switch(rng);
+        self.regimes[self.current].bar(open, &draw_shocks(rng))
+    }
+
+    fn next_bar_with(
+        &mut self,
+        rng: &mut dyn RngCore,
+        open: f64,
+        shocks: &Shocks,
+    ) -> Option<Candle> {
+        self.switch(rng);
+        Some(self.regimes[self.current].bar(open, shocks))
+    }
+
+    fn regime(&self) -> Option<&str> {
+        Some(&self.names[self.current])
+    }
+}
+
+impl PriceModel {
+    /// The same model with an annualized `volatility`; only the continuous models have one.
+    pub fn with_volatility(&self, volatility: f64) -> anyhow::Result<PriceModel> {
+        let mut model = self.clone();
+        match &mut model {
+            PriceModel::Gbm { volatility: v, .. }
+            | PriceModel::JumpDiffusion { volatility: v, .. } => *v = volatility,
+            _ => anyhow::bail!("a covariance matrix needs the Gbm or JumpDiffusion price model"),
+        }
+        Ok(model)
+    }
+
+    pub fn generator(&self, interval_ms: u64) -> Box<dyn BarGenerator> {
+        match *self {
+            PriceModel::Uniform { vol } => Box::new(Uniform { vol }),
+            PriceModel::Gbm { drift, volatility } => {
+                Box::new(Gbm::new(drift, volatility, interval_ms))
+            }
+            PriceModel::JumpDiffusion {
+                drift,
+                volatility,
+                jump_intensity,
+                jump_mean,
+                jump_std,
+            } => Box::new(JumpDiffusion::new(
+                drift,
+                volatility,
+                jump_intensity,
+                jump_mean,
+                jump_std,
+                interval_ms,
+            )),
+            PriceModel::Garch { omega, alpha, beta } => Box::new(Garch::new(omega, alpha, beta)),
+            PriceModel::RegimeSwitching { ref regimes, ref transitions } => {
+                Box::new(RegimeSwitching::new(regimes, transitions.clone(), interval_ms))
+            }
+        }
+    }
+}
+
+/// Compounds sub-step log returns from `open`; the extremes of the path are the wicks.
+fn trace(open: f64, log_returns: impl Iterator<Item = f64>) -> Candle {
+    let (mut high, mut low, mut price) = (open, open, open);
+    for r in log_returns {
+        price *= r.exp();
+        high = high.max(price);
+        low = low.min(price);
+    }
+    Candle { high, low, close: price }
+}
+
+fn normal(rng: &mut dyn RngCore) -> f64 {
+    rng.sample(StandardNormal)
+}
+
+fn draw_shocks(rng: &mut dyn RngCore) -> Shocks {
+    std::array::from_fn(|_| normal(rng))
+}
+
+fn years(interval_ms: u64) -> f64 {
+    interval_ms as f64 / MS_PER_YEAR
+}
+
 pub fn train_test_split(bars: &[Bar], split: f32) -> (&[Bar], &[Bar]) {
     let idx = ((bars.len() as f32) * split).round() as usize;
     let idx = idx.min(bars.len());
     (&bars[..idx], &bars[idx..])
 }
+
+/// Merges per-symbol series into time steps: each step holds every bar sharing a timestamp,
+/// in ascending time order, so several symbols can be driven on one clock.
+pub fn align_bars(series: Vec<Vec<Bar>>) -> Vec<Vec<Bar>> {
+    let mut steps: BTreeMap<DateTime<Utc>, Vec<Bar>> = BTreeMap::new();
+    for bars in series {
+        for b in bars {
+            steps.entry(b.ts).or_default().push(b);
+        }
+    }
+    steps.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(l: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        let n = l.len();
+        (0..n).map(|i| (0..n).map(|j| (0..n).map(|k| l[i][k] * l[j][k]).sum()).collect()).collect()
+    }
+
+    fn assert_matrix_close(actual: &[Vec<f64>], expected: &[Vec<f64>]) {
+        for (a, e) in actual.iter().flatten().zip(expected.iter().flatten()) {
+            assert!((a - e).abs() < 1e-12, "{actual:?} != {expected:?}");
+        }
+    }
+
+    fn sample_correlation(a: &[f64], b: &[f64]) -> f64 {
+        let mean = |x: &[f64]| x.iter().sum::<f64>() / x.len() as f64;
+        let (ma, mb) = (mean(a), mean(b));
+        let cov: f64 = a.iter().zip(b).map(|(x, y)| (x - ma) * (y - mb)).sum();
+        let var = |x: &[f64], m: f64| x.iter().map(|v| (v - m).powi(2)).sum::<f64>();
+        cov / (var(a, ma) * var(b, mb)).sqrt()
+    }
+
+    fn std_dev(x: &[f64]) -> f64 {
+        let mean = x.iter().sum::<f64>() / x.len() as f64;
+        (x.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / x.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn cholesky_factors_multiply_back_to_the_matrix() {
+        let matrix = vec![vec![1.0, 0.5, 0.2], vec![0.5, 1.0, 0.3], vec![0.2, 0.3, 1.0]];
+        let l = cholesky(&matrix).unwrap();
+        assert!((0..3).all(|i| (i + 1..3).all(|j| l[i][j] == 0.0)), "not lower-triangular");
+        assert_matrix_close(&product(&l), &matrix);
+    }
+
+    #[test]
+    fn perfectly_correlated_markets_are_accepted() {
+        let matrix = vec![vec![1.0, 1.0, 0.5], vec![1.0, 1.0, 0.5], vec![0.5, 0.5, 1.0]];
+        let l = cholesky(&matrix).unwrap();
+        assert_eq!(l[1], [1.0, 0.0, 0.0]);
+        assert_matrix_close(&product(&l), &matrix);
+    }
+
+    #[test]
+    fn asymmetric_and_indefinite_matrices_are_rejected() {
+        let err = |matrix: Vec<Vec<f64>>| cholesky(&matrix).unwrap_err().to_string();
+        assert_eq!(err(vec![vec![1.0, 0.5], vec![0.2, 1.0]]), "matrix must be symmetric");
+        assert_eq!(err(vec![vec![1.0, 0.5]]), "matrix must be square");
+        let indefinite = "matrix must be positive semi-definite";
+        assert_eq!(err(vec![vec![1.0, 2.0], vec![2.0, 1.0]]), indefinite);
+        // Fails on the degenerate column the first two rows leave behind
+        let matrix = vec![vec![1.0, 1.0, 0.0], vec![1.0, 1.0, 0.5], vec![0.0, 0.5, 1.0]];
+        assert_eq!(err(matrix), indefinite);
+    }
+
+    #[test]
+    fn covariance_splits_into_correlation_and_volatilities() {
+        let dependence =
+            Dependence::Covariance { matrix: vec![vec![0.04, -0.03], vec![-0.03, 0.09]] };
+        let (correlation, vols) = dependence.split();
+        assert_matrix_close(&correlation, &[vec![1.0, -0.5], vec![-0.5, 1.0]]);
+        assert_matrix_close(&[vols.unwrap()], &[vec![0.2, 0.3]]);
+    }
+
+    #[test]
+    fn generated_returns_have_the_requested_correlation() {
+        let symbols = ["AAA".to_string(), "BBB".to_string()];
+        let start = "2024-01-02T00:00:00Z".parse().unwrap();
+        let load = |dependence: Dependence| {
+            let mut source = SyntheticSource::new(42, start, 60_000, 5000)
+                .with_model(PriceModel::Gbm { drift: 0.0, volatility: 0.2 })
+                .with_dependence(Some(dependence));
+            let series = source.load(&symbols).unwrap();
+            let returns: Vec<Vec<f64>> = series
+                .iter()
+                .map(|bars| bars.iter().map(|b| (b.close / b.open).ln()).collect())
+                .collect();
+            (series, returns)
+        };
+
+        let correlation = Dependence::Correlation { matrix: vec![vec![1.0, 0.7], vec![0.7, 1.0]] };
+        let (series, returns) = load(correlation.clone());
+        let sample = sample_correlation(&returns[0], &returns[1]);
+        assert!((sample - 0.7).abs() < 0.03, "sample correlation {sample}");
+        // The same seed draws the same bars
+        assert_eq!(
+            serde_json::to_value(&load(correlation).0).unwrap(),
+            serde_json::to_value(&series).unwrap()
+        );
+
+        let covariance =
+            Dependence::Covariance { matrix: vec![vec![0.04, -0.03], vec![-0.03, 0.09]] };
+        let (_, returns) = load(covariance);
+        let sample = sample_correlation(&returns[0], &returns[1]);
+        assert!((sample + 0.5).abs() < 0.03, "sample correlation {sample}");
+        let ratio = std_dev(&returns[1]) / std_dev(&returns[0]);
+        assert!((ratio - 1.5).abs() < 0.05, "volatility ratio {ratio}");
+    }
+}
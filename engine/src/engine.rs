@@ -1,116 +1,1170 @@
-use crate::{data::*, strategy::*, types::*};
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crate::{
+    channel::*, checkpoint::*, clock::*, data::*, error::*, execution::*, feed::*, fees::*,
+    history::*, journal::*, metrics::*, report::*, risk::*, sizing::*, strategy::*,
+    stream::Fanout, types::*,
+};
+#[cfg(feature = "columnar")]
+use crate::columnar::*;
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::Duration;
+use rand::Rng;
 
-#[derive(Clone, Debug, serde::Serialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum EngineEvent {
     Bar(Bar),
     Order(Order),
     Trade(Trade),
+    Cancel(Cancel),
+    MarginCall(MarginCall),
+    /// Cash, marked positions and equity at the end of each time step.
+    Portfolio(Portfolio),
     Metrics(Metrics),
-    Done,
+    /// Engine state to resume from, on `EngineControl::Checkpoint` or every
+    /// `checkpoint_every` steps.
+    Checkpoint(Box<Checkpoint>),
+    /// Final report of the run, sent right before `Done` unless the run failed.
+    Summary(Box<RunReport>),
+    /// Why the run failed, sent right before `Done(RunStatus::Failed)`.
+    Error(EngineError),
+    /// Always the last event of a run.
+    Done(RunStatus),
+}
+
+/// How a run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum RunStatus {
+    /// Every step was processed.
+    Completed,
+    /// `EngineControl::Stop` ended the run early.
+    Stopped,
+    /// Training failed or the engine panicked; see the preceding `Error` event.
+    Failed,
 }
 
 pub struct EngineHandle {
     pub tx_ctrl: Sender<EngineControl>,
     pub rx_evt: Receiver<EngineEvent>,
+    pub drops: Arc<DropCounters>,
+    pub(crate) fanout: Arc<Fanout>,
 }
 
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum EngineControl {
     Stop,
+    Pause,
+    Resume,
+    /// Advances `n` time steps and then holds, pausing the run if it was not already.
+    Step(u64),
+    /// Playback speed relative to `bar_interval_ms`; 2.0 runs twice as fast.
+    SetSpeed(f64),
+    /// Emits an `EngineEvent::Checkpoint` before the next step, even while paused.
+    Checkpoint,
 }
 
 pub struct Engine;
 
 impl Engine {
+    /// Runs the default composition described by `config`.
     pub fn spawn(config: EngineConfig) -> EngineHandle {
-        let (tx_evt, rx_evt) = unbounded();
+        EngineBuilder::new(config).spawn()
+    }
+
+    /// Continues a default-composition run from `checkpoint`.
+    pub fn resume(checkpoint: Checkpoint) -> EngineHandle {
+        EngineBuilder::new(checkpoint.config.clone()).resume(checkpoint)
+    }
+
+    /// Starts composing an engine with custom parts.
+    pub fn builder(config: EngineConfig) -> EngineBuilder {
+        EngineBuilder::new(config)
+    }
+
+    /// Re-emits a recorded run. `speed` scales the recorded pace (1.0 is the original);
+    /// `None` replays as fast as the reader takes events. Pause, step, speed and stop
+    /// controls apply per recorded step.
+    pub fn replay(journal: JournalReader, speed: Option<f64>) -> EngineHandle {
+        let (mut tx_evt, rx_evt, drops) = event_channel(None, Backpressure::Block);
         let (tx_ctrl, rx_ctrl) = unbounded();
 
         std::thread::spawn(move || {
-            let mut strategy = RfStrategy::new(config.rf_trees, config.rf_max_depth);
-
-            // Synthetic data for first symbol only for MVP
-            let symbol = config.symbols.get(0).cloned().unwrap_or_else(|| "SIM".into());
-            let bars = generate_synthetic_bars(&symbol, 100.0, config.bar_interval_ms, config.length_bars, 0.01);
-            let (train, test) = train_test_split(&bars, config.train_split);
-            let _ = strategy.train(train);
-
-            let mut cash = config.starting_cash;
-            let mut qty: f64 = 0.0;
-            let mut entry_price = 0.0;
-            let mut wins = 0usize;
-            let mut losses = 0usize;
-            let mut pnl = 0.0f64;
-            let mut peak_equity = cash;
-
-            for b in test.iter().cloned() {
-                if let Ok(ctrl) = rx_ctrl.try_recv() {
-                    match ctrl {
-                        EngineControl::Stop => break,
+            let mut journal = journal;
+            let mut controller = Controller::new(rx_ctrl);
+            let mut clock: Box<dyn Clock> = match speed {
+                Some(speed) => {
+                    controller.apply(EngineControl::SetSpeed(speed));
+                    Box::new(ReplayClock::default())
+                }
+                None => Box::new(InstantClock),
+            };
+
+            // A journal cut short by a crash has no Done of its own
+            let mut status = RunStatus::Stopped;
+            'steps: loop {
+                let step = match journal.next_step() {
+                    Ok(step) if step.is_empty() => break,
+                    Ok(step) => step,
+                    Err(e) => {
+                        tx_evt.send(EngineEvent::Error(EngineError::Journal(e.to_string())));
+                        status = RunStatus::Failed;
+                        break;
+                    }
+                };
+                let ts = step[0].recorded_at;
+                loop {
+                    match controller.advance(clock.as_ref(), ts) {
+                        Advance::Run => break,
+                        Advance::Checkpoint => {}
+                        Advance::Stop => {
+                            status = RunStatus::Stopped;
+                            break 'steps;
+                        }
                     }
                 }
+                clock.tick(ts);
 
-                let long = strategy.decide(&b);
-
-                if long && qty == 0.0 {
-                    // enter long with 10% cash
-                    let spend = cash * 0.10;
-                    qty = spend / b.close;
-                    entry_price = b.close;
-                    cash -= spend;
-                    let _ = tx_evt.send(EngineEvent::Order(Order {
-                        id: 0,
-                        symbol: symbol.clone(),
-                        side: Side::Buy,
-                        qty,
-                        ty: OrderType::Market,
-                    }));
-                } else if !long && qty > 0.0 {
-                    // exit position
-                    let proceeds = qty * b.close;
-                    let trade_pnl = proceeds - (qty * entry_price);
-                    pnl += trade_pnl;
-                    if trade_pnl >= 0.0 {
-                        wins += 1;
-                    } else {
-                        losses += 1;
+                for entry in step {
+                    match entry.event {
+                        // Re-sent once the replay itself ends
+                        EngineEvent::Done(recorded) => status = recorded,
+                        evt => tx_evt.send(evt),
                     }
-                    cash += proceeds;
-                    qty = 0.0;
-                    let _ = tx_evt.send(EngineEvent::Order(Order {
-                        id: 0,
-                        symbol: symbol.clone(),
-                        side: Side::Sell,
-                        qty: 0.0,
-                        ty: OrderType::Market,
-                    }));
-                }
-
-                let equity = cash + qty * b.close;
-                if equity > peak_equity {
-                    peak_equity = equity;
-                }
-                let dd = (peak_equity - equity) / peak_equity.max(1.0);
-
-                let m = Metrics {
-                    pnl,
-                    max_drawdown: dd,
-                    sharpe: 0.0, // compute later with returns vector
-                    win_rate: if wins + losses > 0 {
-                        wins as f64 / (wins + losses) as f64
-                    } else {
-                        0.0
-                    },
-                    trades: wins + losses,
+                }
+            }
+            tx_evt.send(EngineEvent::Done(status));
+        });
+
+        EngineHandle { tx_ctrl, rx_evt, drops, fanout: Default::default() }
+    }
+}
+
+/// Composes an engine from its parts. Parts left out are built from the config as
+/// `Engine::spawn` builds them: synthetic bars, `RfStrategy`, `SimBroker` and `MarginRisk`.
+pub struct EngineBuilder {
+    config: EngineConfig,
+    data: Option<Box<dyn DataSource>>,
+    feed: Option<Box<dyn Feed>>,
+    strategy: Option<Box<dyn Strategy>>,
+    execution: Option<Box<dyn ExecutionModel>>,
+    risk: Option<Box<dyn RiskManager>>,
+}
+
+impl EngineBuilder {
+    pub fn new(config: EngineConfig) -> Self {
+        Self { config, data: None, feed: None, strategy: None, execution: None, risk: None }
+    }
+
+    pub fn with_data(mut self, data: impl DataSource + 'static) -> Self {
+        self.data = Some(Box::new(data));
+        self
+    }
+
+    /// Live bars for a `Mode::Forwardtest` run. All of the data source's bars then train
+    /// the strategy, and the run trades each step as the feed delivers it until the feed
+    /// closes. Steps no later than the last one traded are skipped, so a resumed run may
+    /// be fed from before its checkpoint.
+    pub fn with_feed(mut self, feed: impl Feed + 'static) -> Self {
+        self.feed = Some(Box::new(feed));
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: impl Strategy + 'static) -> Self {
+        self.strategy = Some(Box::new(strategy));
+        self
+    }
+
+    pub fn with_execution(mut self, execution: impl ExecutionModel + 'static) -> Self {
+        self.execution = Some(Box::new(execution));
+        self
+    }
+
+    pub fn with_risk(mut self, risk: impl RiskManager + 'static) -> Self {
+        self.risk = Some(Box::new(risk));
+        self
+    }
+
+    pub fn spawn(self) -> EngineHandle {
+        self.launch(None)
+    }
+
+    /// Continues a run from `checkpoint` with this builder's parts, which must match the
+    /// original run's. Events from the checkpoint's step onwards, and the final report
+    /// apart from its timing, match those of the uninterrupted run.
+    pub fn resume(self, checkpoint: Checkpoint) -> EngineHandle {
+        self.launch(Some(checkpoint))
+    }
+
+    fn launch(self, checkpoint: Option<Checkpoint>) -> EngineHandle {
+        let (mut tx_evt, rx_evt, drops) =
+            event_channel(self.config.event_buffer, self.config.backpressure);
+        let (tx_ctrl, rx_ctrl) = unbounded();
+
+        std::thread::spawn(move || {
+            // A failed run still ends with Done so consumers never wait on a dead engine
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                run(self, checkpoint, rx_ctrl, &mut tx_evt)
+            }));
+            let status = match outcome {
+                Ok(Ok(status)) => status,
+                Ok(Err(err)) => {
+                    tx_evt.send(EngineEvent::Error(err));
+                    RunStatus::Failed
+                }
+                Err(payload) => {
+                    tx_evt.send(EngineEvent::Error(EngineError::from_panic(payload)));
+                    RunStatus::Failed
+                }
+            };
+            tx_evt.send(EngineEvent::Done(status));
+        });
+
+        EngineHandle { tx_ctrl, rx_evt, drops, fanout: Default::default() }
+    }
+}
+
+/// Runs one engine from data generation to the final report, picking up at `checkpoint`
+/// when given. Returns how the run ended; errors are reported by the caller before `Done`.
+fn run(
+    parts: EngineBuilder,
+    checkpoint: Option<Checkpoint>,
+    rx_ctrl: Receiver<EngineControl>,
+    tx_evt: &mut EventSender,
+) -> Result<RunStatus, EngineError> {
+    let started_at = Utc::now();
+    let wall = std::time::Instant::now();
+    let EngineBuilder { config, data, feed, strategy, execution, risk } = parts;
+    config.validate()?;
+    let forward = matches!(config.mode, Mode::Forwardtest);
+    if feed.is_some() && !forward {
+        return Err(EngineError::Data("only Forwardtest runs trade from a feed".into()));
+    }
+    // Loaded history has closed long ago, so without a feed there is nothing to wait for
+    if forward && feed.is_none() && !matches!(config.data_source, BarSource::Synthetic) {
+        let msg = "Forwardtest on loaded history needs a live feed to trade";
+        return Err(EngineError::Data(msg.into()));
+    }
+    if let Some(path) = &config.journal {
+        let journal = match checkpoint.as_ref().and_then(|cp| cp.journal_seq) {
+            Some(next_seq) => JournalWriter::resume(path, next_seq),
+            None => JournalWriter::create(path),
+        };
+        tx_evt.set_journal(journal.map_err(|e| EngineError::Journal(e.to_string()))?);
+    }
+
+    // Without a seed every run draws fresh entropy, as before. A resumed run reuses the
+    // seed and start its data was first generated from.
+    let seed = match &checkpoint {
+        Some(cp) => cp.seed,
+        None => config.seed.unwrap_or_else(|| rand::thread_rng().gen()),
+    };
+    let mut strategy = strategy.unwrap_or_else(|| {
+        Box::new(
+            RfStrategy::new(config.rf_trees, config.rf_max_depth)
+                .with_seed(seed)
+                .with_shorts(config.allow_short),
+        )
+    });
+
+    let symbols = if config.symbols.is_empty() {
+        vec!["SIM".to_string()]
+    } else {
+        config.symbols.clone()
+    };
+
+    // One series per symbol on a shared time grid; the strategy is trained on every
+    // symbol's history and then driven step by step across all of them.
+    let interval = Duration::from_millis(config.bar_interval_ms);
+    // A live feed brings the test bars, so the whole history trains the strategy
+    let train_split = if feed.is_some() { 1.0 } else { config.train_split };
+    // A forward test's history ends now, so its first test bar is the next one to close
+    let start = match &checkpoint {
+        Some(cp) => cp.start,
+        None => config.start_ts.unwrap_or_else(|| match config.mode {
+            Mode::Forwardtest => {
+                let history = (config.length_bars as f32 * train_split).round() as i64;
+                Utc::now() - chrono::Duration::milliseconds(config.bar_interval_ms as i64 * history)
+            }
+            _ => Utc::now(),
+        }),
+    };
+    let mut data = match data {
+        Some(data) => data,
+        None => default_source(&config, seed, start)?,
+    };
+    let series = data.load(&symbols).map_err(|e| EngineError::Data(e.to_string()))?;
+    // Kept only to be written out with the report
+    #[cfg(feature = "columnar")]
+    let loaded = if config.export.is_some() { series.concat() } else { Vec::new() };
+    let mut train_bars = Vec::new();
+    let mut test_series = Vec::with_capacity(series.len());
+    for bars in series {
+        let (train, test) = train_test_split(&bars, train_split);
+        train_bars.extend_from_slice(train);
+        test_series.push(test.to_vec());
+    }
+    // Regime labels are ground truth for analysis; the strategy must not see them
+    for b in &mut train_bars {
+        b.regime = None;
+    }
+    let steps = align_bars(test_series);
+
+    let mut broker = execution.unwrap_or_else(|| {
+        Box::new(
+            SimBroker::new()
+                .with_slippage(config.slippage.clone())
+                .with_max_participation(config.max_volume_participation)
+                .with_assets(config.assets.clone()),
+        )
+    });
+    let risk = risk.unwrap_or_else(|| {
+        Box::new(
+            MarginRisk::new(config.margin.clone(), config.sizing.sizer())
+                .with_assets(config.assets.clone()),
+        )
+    });
+
+    let checkpoint_err = |e: anyhow::Error| EngineError::Checkpoint(e.to_string());
+    let (first, mut account) = match checkpoint {
+        Some(cp) => {
+            strategy.restore(cp.strategy).map_err(checkpoint_err)?;
+            broker.restore(cp.execution).map_err(checkpoint_err)?;
+            (cp.cursor, cp.account)
+        }
+        None => {
+            strategy.train(&train_bars).map_err(|e| EngineError::Training(e.to_string()))?;
+            let account = Account::new(
+                config.starting_cash,
+                config.commission.clone(),
+                MetricsTracker::new(config.starting_cash, config.bar_interval_ms),
+            );
+            (0, account)
+        }
+    };
+    // `journal_seq` is the entry the checkpoint itself is about to be journaled as
+    let snapshot = |cursor: usize,
+                    account: &Account,
+                    broker: &dyn ExecutionModel,
+                    journal_seq: Option<u64>| {
+        Ok::<_, EngineError>(EngineEvent::Checkpoint(Box::new(Checkpoint {
+            config: config.clone(),
+            seed,
+            start,
+            cursor,
+            strategy: strategy.state().map_err(checkpoint_err)?,
+            execution: broker.state().map_err(checkpoint_err)?,
+            journal_seq: journal_seq.map(|seq| seq + 1),
+            account: account.clone(),
+        })))
+    };
+
+    let mut feed = feed.unwrap_or_else(|| {
+        let steps = StepFeed::new(steps.into_iter().skip(first));
+        if forward {
+            Box::new(steps.released_at_close(interval))
+        } else {
+            Box::new(steps)
+        }
+    });
+    let mut controller = Controller::new(rx_ctrl);
+    let mut clock = clock_for(&config.mode, interval);
+
+    let mut status = RunStatus::Completed;
+    let mut cursor = first;
+    let mut last_ts = account.equity_curve.last().map(|p| p.ts);
+    'steps: loop {
+        let mut step = match feed.next_step(FEED_WAIT) {
+            FeedStep::Bars(step) => step,
+            FeedStep::Closed => break,
+            // Controls still apply while the feed has nothing new
+            FeedStep::Waiting => {
+                match controller.poll() {
+                    Advance::Run => {}
+                    Advance::Checkpoint => {
+                        let journal_seq = tx_evt.journal_seq();
+                        tx_evt.send(snapshot(cursor, &account, broker.as_ref(), journal_seq)?)
+                    }
+                    Advance::Stop => {
+                        status = RunStatus::Stopped;
+                        break;
+                    }
+                }
+                continue;
+            }
+        };
+        let Some(ts) = check_step(&step, &symbols, last_ts)? else {
+            continue;
+        };
+        last_ts = Some(ts);
+        // Held back from the strategy and restored on the emitted bars
+        let regimes: Vec<_> = step.iter_mut().map(|b| b.regime.take()).collect();
+        loop {
+            match controller.advance(clock.as_ref(), ts) {
+                Advance::Run => break,
+                Advance::Checkpoint => {
+                    let journal_seq = tx_evt.journal_seq();
+                    tx_evt.send(snapshot(cursor, &account, broker.as_ref(), journal_seq)?)
+                }
+                Advance::Stop => {
+                    status = RunStatus::Stopped;
+                    break 'steps;
+                }
+            }
+        }
+        clock.tick(ts);
+
+        // Resting orders trade against the new bars before the strategy reacts to them
+        for b in &step {
+            account.process(broker.on_bar(b), tx_evt);
+            account.portfolio.mark(&b.symbol, b.close);
+        }
+
+        account.portfolio.update_equity();
+        let margin_call = risk.margin_call(&account.portfolio, ts);
+        let called = margin_call.is_some();
+        if let Some(call) = margin_call {
+            tx_evt.send(EngineEvent::MarginCall(call));
+            // Forced liquidation of every position and every resting order
+            for symbol in &symbols {
+                account.process(broker.cancel_symbol(symbol, ts), tx_evt);
+            }
+            for pos in account.portfolio.positions.clone() {
+                // Symbols without a bar this step close at their last mark
+                let mark = pos.avg_price + pos.unrealized_pnl / pos.qty;
+                let bar = step.iter().find(|b| b.symbol == pos.symbol).cloned().unwrap_or(Bar {
+                    ts,
+                    symbol: pos.symbol.clone(),
+                    open: mark,
+                    high: mark,
+                    low: mark,
+                    close: mark,
+                    volume: f64::INFINITY,
+                    regime: None,
+                });
+                let side = if pos.qty > 0.0 { Side::Sell } else { Side::Buy };
+                let order = Order::market(&pos.symbol, side, pos.qty.abs());
+                account.process(broker.submit(order, &bar), tx_evt);
+            }
+        }
+
+        for b in &step {
+            // Let a partially filled entry or exit finish before acting again
+            let working = broker
+                .open_orders()
+                .iter()
+                .any(|o| o.symbol == b.symbol && o.parent_id.is_none());
+            if working {
+                continue;
+            }
+
+            let target = match strategy.decide(b) {
+                Signal::Long => 1.0,
+                Signal::Short if config.allow_short => -1.0,
+                _ => 0.0,
+            };
+            let held = account.portfolio.qty(&b.symbol);
+            let current = if held > 0.0 {
+                1.0
+            } else if held < 0.0 {
+                -1.0
+            } else {
+                0.0
+            };
+            if current == target {
+                continue;
+            }
+
+            if held != 0.0 {
+                // exit the whole position and drop its bracket legs
+                let side = if held > 0.0 { Side::Sell } else { Side::Buy };
+                account.process(broker.cancel_symbol(&b.symbol, b.ts), tx_evt);
+                account.process(
+                    broker.submit(Order::market(&b.symbol, side, held.abs()), b),
+                    tx_evt,
+                );
+            }
+
+            // No new entries for the rest of a step that ended in a margin call
+            if target != 0.0 && !called && account.portfolio.qty(&b.symbol) == 0.0 {
+                account.portfolio.update_equity();
+                let bracket = config.bracket.as_ref().map(|bc| Bracket {
+                    take_profit: b.close * (1.0 + target * bc.take_profit_pct),
+                    stop_loss: b.close * (1.0 - target * bc.stop_loss_pct),
+                });
+                let ctx = SizingContext {
+                    portfolio: &account.portfolio,
+                    bar: b,
+                    stop_distance: bracket.as_ref().map(|br| (b.close - br.stop_loss).abs()),
                 };
+                let qty = risk.entry_qty(&ctx);
+                if qty <= 0.0 {
+                    continue;
+                }
+                let side = if target > 0.0 { Side::Buy } else { Side::Sell };
+                let mut order = config.entry.order(b, side, qty);
+                if let Some(bracket) = bracket {
+                    order = order.with_bracket(bracket);
+                }
+                account.process(broker.submit(order, b), tx_evt);
+            }
+        }
+
+        let open: Vec<u64> = broker.open_orders().iter().map(|o| o.id).collect();
+        account.order_fees.retain(|id, _| open.contains(id));
+
+        let equity = account.portfolio.update_equity();
+        let invested = !account.portfolio.positions.is_empty();
+        account.tracker.on_step(equity, invested);
+        let m = account.tracker.snapshot(account.pnl, account.fees);
+        account.equity_curve.push(EquityPoint { ts, equity });
+
+        for (mut b, regime) in step.into_iter().zip(regimes) {
+            b.regime = regime;
+            tx_evt.send(EngineEvent::Bar(b));
+        }
+        tx_evt.send(EngineEvent::Portfolio(account.portfolio.clone()));
+        tx_evt.send(EngineEvent::Metrics(m));
+
+        if config.checkpoint_every.is_some_and(|every| (cursor as u64 + 1).is_multiple_of(every)) {
+            let journal_seq = tx_evt.journal_seq();
+            tx_evt.send(snapshot(cursor + 1, &account, broker.as_ref(), journal_seq)?);
+        }
+        cursor += 1;
+    }
+
+    let report = RunReport {
+        metrics: account.tracker.snapshot(account.pnl, account.fees),
+        trades: account.ledger.closed,
+        equity_curve: account.equity_curve,
+        timing: (config.seed.is_none() || config.start_ts.is_none()).then(|| RunTiming {
+            started_at,
+            finished_at: Utc::now(),
+            elapsed_ms: wall.elapsed().as_millis() as u64,
+        }),
+        config,
+    };
+    #[cfg(feature = "columnar")]
+    if let Some(export) = &report.config.export {
+        let bars = format!("bars.{}", export.format.extension());
+        report
+            .export(&export.dir, export.format)
+            .and_then(|()| write_bars(std::path::Path::new(&export.dir).join(bars), &loaded))
+            .map_err(|e| EngineError::Export(e.to_string()))?;
+    }
+    tx_evt.send(EngineEvent::Summary(Box::new(report)));
+    Ok(status)
+}
+
+/// The data source a config asks for, used when the builder was given none.
+fn default_source(
+    config: &EngineConfig,
+    seed: u64,
+    start: DateTime<Utc>,
+) -> Result<Box<dyn DataSource>, EngineError> {
+    Ok(match &config.data_source {
+        BarSource::Synthetic => Box::new(
+            SyntheticSource::new(seed, start, config.bar_interval_ms, config.length_bars)
+                .with_model(config.price_model.clone())
+                .with_dependence(config.dependence.clone()),
+        ),
+        BarSource::Csv(csv) => Box::new(CsvSource::new((**csv).clone())),
+        #[cfg(feature = "columnar")]
+        BarSource::Columnar { paths } => Box::new(ColumnarSource::new(paths.clone())),
+        #[cfg(not(feature = "columnar"))]
+        BarSource::Columnar { .. } => {
+            let msg = "Parquet and Arrow files need the `columnar` feature";
+            return Err(EngineError::Data(msg.into()));
+        }
+    })
+}
+
+/// How long the run loop waits on its feed before handling controls again.
+const FEED_WAIT: Duration = Duration::from_millis(50);
+
+/// The timestamp of a step from the feed, or `None` for a step no later than the last one
+/// traded. Steps must hold one bar per listed symbol at most, all at the same time.
+fn check_step(
+    step: &[Bar],
+    symbols: &[String],
+    last_ts: Option<DateTime<Utc>>,
+) -> Result<Option<DateTime<Utc>>, EngineError> {
+    let Some(ts) = step.first().map(|b| b.ts) else {
+        return Err(EngineError::Data("the feed sent a step without bars".into()));
+    };
+    if last_ts.is_some_and(|last| ts <= last) {
+        return Ok(None);
+    }
+    for (i, b) in step.iter().enumerate() {
+        let msg = if b.ts != ts {
+            format!("the step at {ts} holds a bar at {}", b.ts)
+        } else if !symbols.contains(&b.symbol) {
+            format!("the step at {ts} holds {}, which the run does not trade", b.symbol)
+        } else if step[..i].iter().any(|o| o.symbol == b.symbol) {
+            format!("the step at {ts} holds two bars for {}", b.symbol)
+        } else {
+            continue;
+        };
+        return Err(EngineError::Data(msg));
+    }
+    Ok(Some(ts))
+}
+
+/// What the run loop does next, as decided by `Controller::advance`.
+enum Advance {
+    Run,
+    /// Emit a checkpoint, then ask again.
+    Checkpoint,
+    Stop,
+}
+
+/// Run-state driven by `EngineControl` messages: pausing, stepping and playback speed.
+struct Controller {
+    rx: Receiver<EngineControl>,
+    paused: bool,
+    step_budget: u64,
+    speed: f64,
+    checkpoint_requested: bool,
+}
 
-                let _ = tx_evt.send(EngineEvent::Bar(b));
-                let _ = tx_evt.send(EngineEvent::Metrics(m));
+impl Controller {
+    fn new(rx: Receiver<EngineControl>) -> Self {
+        Self {
+            rx,
+            paused: false,
+            step_budget: 0,
+            speed: 1.0,
+            checkpoint_requested: false,
+        }
+    }
+
+    /// Applies one control message; returns false when the run must stop.
+    fn apply(&mut self, ctrl: EngineControl) -> bool {
+        match ctrl {
+            EngineControl::Stop => return false,
+            EngineControl::Pause => {
+                self.paused = true;
+                self.step_budget = 0;
+            }
+            EngineControl::Resume => {
+                self.paused = false;
+                self.step_budget = 0;
             }
+            EngineControl::Step(n) => {
+                self.paused = true;
+                self.step_budget = n;
+            }
+            EngineControl::SetSpeed(multiplier) => {
+                if multiplier.is_finite() && multiplier > 0.0 {
+                    self.speed = multiplier;
+                }
+            }
+            EngineControl::Checkpoint => self.checkpoint_requested = true,
+        }
+        true
+    }
 
-            let _ = tx_evt.send(EngineEvent::Done);
+    /// Applies the control messages that have arrived, without waiting for more.
+    /// `Advance::Run` means there is neither a stop nor a checkpoint to handle.
+    fn poll(&mut self) -> Advance {
+        while let Ok(ctrl) = self.rx.try_recv() {
+            if !self.apply(ctrl) {
+                return Advance::Stop;
+            }
+        }
+        if std::mem::take(&mut self.checkpoint_requested) {
+            return Advance::Checkpoint;
+        }
+        Advance::Run
+    }
+
+    /// Blocks until the step stamped `ts` may run: honours pause and step requests and
+    /// holds the step until `clock` releases it. Checkpoint requests are handed back to
+    /// the run loop as they arrive, even while paused.
+    fn advance(&mut self, clock: &dyn Clock, ts: DateTime<Utc>) -> Advance {
+        loop {
+            match self.poll() {
+                Advance::Run => {}
+                other => return other,
+            }
+
+            if self.paused {
+                // Steps requested while paused run back to back
+                if self.step_budget > 0 {
+                    self.step_budget -= 1;
+                    return Advance::Run;
+                }
+                // Nobody left to resume a paused run
+                match self.rx.recv() {
+                    Ok(ctrl) => {
+                        if !self.apply(ctrl) {
+                            return Advance::Stop;
+                        }
+                    }
+                    Err(_) => return Advance::Stop,
+                }
+                continue;
+            }
+
+            let remaining = clock.remaining(ts, self.speed);
+            if remaining.is_zero() {
+                return Advance::Run;
+            }
+            match self.rx.recv_timeout(remaining) {
+                Ok(ctrl) => {
+                    if !self.apply(ctrl) {
+                        return Advance::Stop;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => std::thread::sleep(remaining),
+            }
+        }
+    }
+}
+
+/// The run's portfolio together with the statistics reported in `Metrics`.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct Account {
+    portfolio: Portfolio,
+    commission: CommissionModel,
+    tracker: MetricsTracker,
+    ledger: TradeLedger,
+    equity_curve: Vec<EquityPoint>,
+    pnl: f64,
+    fees: f64,
+    traded_volume: f64,
+    /// Charges so far of orders filled this step or still working.
+    #[serde(default)]
+    order_fees: BTreeMap<u64, OrderFees>,
+}
+
+impl Account {
+    fn new(cash: f64, commission: CommissionModel, tracker: MetricsTracker) -> Self {
+        Self {
+            portfolio: Portfolio::new(cash),
+            commission,
+            tracker,
+            ledger: TradeLedger::default(),
+            equity_curve: Vec::new(),
+            pnl: 0.0,
+            fees: 0.0,
+            traded_volume: 0.0,
+            order_fees: BTreeMap::new(),
+        }
+    }
+
+    /// Books fills against the portfolio and forwards every execution as an event.
+    fn process(&mut self, execs: Vec<Execution>, tx_evt: &mut EventSender) {
+        for exec in execs {
+            let evt = match exec {
+                Execution::Accepted(order) => EngineEvent::Order(order),
+                Execution::Cancelled(cancel) => EngineEvent::Cancel(cancel),
+                Execution::Fill(mut trade) => {
+                    let order = self.order_fees.entry(trade.order_id).or_default();
+                    trade.fee = self.commission.fee(
+                        trade.qty,
+                        trade.price,
+                        trade.liquidity,
+                        self.traded_volume,
+                        order,
+                    );
+                    order.filled += trade.qty;
+                    order.charged += trade.fee;
+                    self.traded_volume += trade.qty * trade.price;
+                    self.fees += trade.fee;
+                    self.portfolio.cash -= trade.fee;
+
+                    let held = self.portfolio.qty(&trade.symbol);
+                    let closing = match trade.side {
+                        Side::Buy => held < 0.0,
+                        Side::Sell => held > 0.0,
+                    };
+                    let realized =
+                        self.portfolio.apply_fill(&trade.symbol, &trade.side, trade.qty, trade.price);
+                    self.ledger.record(&trade);
+                    if closing {
+                        self.pnl += realized;
+                        self.tracker.on_close(realized);
+                    }
+                    EngineEvent::Trade(trade)
+                }
+            };
+            tx_evt.send(evt);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// A reproducible backtest; `extra` overrides or adds config fields.
+    pub(crate) fn config(extra: serde_json::Value) -> EngineConfig {
+        let mut config = serde_json::json!({
+            "symbols": ["AAA", "BBB"],
+            "bar_interval_ms": 60000,
+            "length_bars": 300,
+            "rf_trees": 5,
+            "rf_max_depth": 4,
+            "train_split": 0.5,
+            "mode": "Backtest",
+            "starting_cash": 100000.0,
+            "seed": 7,
+            "start_ts": "2024-01-02T00:00:00Z",
+        });
+        for (key, value) in extra.as_object().unwrap() {
+            config[key] = value.clone();
+        }
+        serde_json::from_value(config).unwrap()
+    }
+
+    /// The run's events as JSON lines.
+    fn json_events(handle: EngineHandle) -> Vec<String> {
+        handle.rx_evt.iter().map(|evt| serde_json::to_string(&evt).unwrap()).collect()
+    }
+
+    #[test]
+    fn seeded_runs_emit_byte_identical_streams() {
+        let config = config(serde_json::json!({
+            "bracket": { "take_profit_pct": 0.01, "stop_loss_pct": 0.01 },
+            "allow_short": true,
+        }));
+        let first = json_events(Engine::spawn(config.clone()));
+        assert!(first.iter().any(|line| line.starts_with(r#"{"Trade""#)));
+        assert!(first.last().unwrap().contains("Completed"));
+        assert_eq!(first, json_events(Engine::spawn(config.clone())));
+
+        // Runs that are not reproducible anyway report how long they took
+        let unpinned = EngineConfig { start_ts: None, ..config };
+        let report = Engine::spawn(unpinned).rx_evt.iter().find_map(|evt| match evt {
+            EngineEvent::Summary(report) => Some(report),
+            _ => None,
         });
+        assert!(report.unwrap().timing.is_some());
+    }
+
+    #[test]
+    fn resumed_runs_continue_exactly_like_uninterrupted_ones() {
+        let config = config(serde_json::json!({
+            "bracket": { "take_profit_pct": 0.005, "stop_loss_pct": 0.005 },
+            "commission": { "PerShare": { "rate": 0.01, "min": 1.0 } },
+            "max_volume_participation": 0.0001,
+            "checkpoint_every": 7,
+            "allow_short": true,
+        }));
+        let uninterrupted = json_events(Engine::spawn(config));
+
+        // Resume where working orders carry over: a partly filled entry and its legs
+        let (at, checkpoint) = uninterrupted
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.starts_with(r#"{"Checkpoint""#))
+            .map(|(at, line)| match serde_json::from_str(line).unwrap() {
+                EngineEvent::Checkpoint(checkpoint) => (at, *checkpoint),
+                other => panic!("expected a checkpoint, got {other:?}"),
+            })
+            .find(|(_, cp)| !cp.account.order_fees.is_empty())
+            .expect("a checkpoint taken mid-order");
+        let resumed = json_events(Engine::resume(checkpoint));
+        assert_eq!(resumed, uninterrupted[at + 1..]);
+    }
+
+    #[test]
+    fn abandoned_runs_finish_and_journal_everything() {
+        let name = format!("nexow-abandoned-{}.jsonl", std::process::id());
+        let journal = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&journal);
+        let config = config(serde_json::json!({ "event_buffer": 4, "journal": journal }));
+        drop(Engine::spawn(config));
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(30);
+        let done = loop {
+            let last = JournalReader::open(&journal).ok().and_then(|j| j.last()?.ok());
+            if let Some(JournalEntry { event: EngineEvent::Done(status), .. }) = last {
+                break status;
+            }
+            assert!(std::time::Instant::now() < deadline, "the engine never finished");
+            std::thread::sleep(Duration::from_millis(20));
+        };
+        assert_eq!(done, RunStatus::Completed);
+        std::fs::remove_file(&journal).unwrap();
+    }
+
+    #[test]
+    fn resumed_runs_rewrite_their_journal_from_the_checkpoint() {
+        let name = format!("nexow-resumed-{}.jsonl", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        let config = config(serde_json::json!({ "checkpoint_every": 50, "journal": path }));
+        Engine::spawn(config).rx_evt.iter().for_each(drop);
+        let entries = |path: &std::path::Path| {
+            let entries = JournalReader::open(path).unwrap().map(|entry| {
+                let JournalEntry { seq, event, .. } = entry.unwrap();
+                (seq, serde_json::to_string(&event).unwrap())
+            });
+            entries.collect::<Vec<_>>()
+        };
+        let original = entries(&path);
+
+        let mut checkpoints =
+            original.iter().filter(|(_, evt)| evt.starts_with(r#"{"Checkpoint""#));
+        let checkpoint = match serde_json::from_str(&checkpoints.nth(1).unwrap().1) {
+            Ok(EngineEvent::Checkpoint(checkpoint)) => *checkpoint,
+            other => panic!("expected a checkpoint, got {other:?}"),
+        };
+        Engine::resume(checkpoint).rx_evt.iter().for_each(drop);
+        assert_eq!(entries(&path), original);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Notes whether any bar it was given carried a regime label.
+    #[derive(Clone, Default)]
+    struct Peeking(Arc<AtomicBool>);
+
+    impl Strategy for Peeking {
+        fn train(&mut self, bars: &[Bar]) -> anyhow::Result<()> {
+            self.0.fetch_or(bars.iter().any(|b| b.regime.is_some()), Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn decide(&self, last: &Bar) -> Signal {
+            self.0.fetch_or(last.regime.is_some(), Ordering::Relaxed);
+            Signal::Long
+        }
+    }
+
+    fn bar(symbol: &str, minute: i64, close: f64) -> Bar {
+        let start: DateTime<Utc> = "2024-01-02T00:00:00Z".parse().unwrap();
+        Bar {
+            ts: start + chrono::Duration::minutes(minute),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1e6,
+            symbol: symbol.to_string(),
+            regime: None,
+        }
+    }
+
+    /// AAA trades every minute; BBB stops after minute 11.
+    struct Staggered;
+
+    impl DataSource for Staggered {
+        fn load(&mut self, _symbols: &[String]) -> anyhow::Result<Vec<Vec<Bar>>> {
+            Ok(vec![
+                (0..20).map(|m| bar("AAA", m, 100.0)).collect(),
+                (0..12).map(|m| bar("BBB", m, 50.0 + m as f64)).collect(),
+            ])
+        }
+    }
+
+    struct AlwaysLong;
+
+    impl Strategy for AlwaysLong {
+        fn train(&mut self, _bars: &[Bar]) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn decide(&self, _last: &Bar) -> Signal {
+            Signal::Long
+        }
+    }
+
+    /// Buys one unit per entry and calls margin at minute 15.
+    struct CallAt15;
+
+    impl RiskManager for CallAt15 {
+        fn entry_qty(&self, _ctx: &SizingContext) -> f64 {
+            1.0
+        }
+
+        fn margin_call(&self, portfolio: &Portfolio, ts: DateTime<Utc>) -> Option<MarginCall> {
+            (ts == bar("AAA", 15, 0.0).ts).then_some(MarginCall {
+                ts,
+                equity: portfolio.equity,
+                requirement: f64::INFINITY,
+            })
+        }
+    }
+
+    #[test]
+    fn margin_call_liquidates_every_position_and_blocks_entries() {
+        let handle = Engine::builder(config(serde_json::json!({})))
+            .with_data(Staggered)
+            .with_strategy(AlwaysLong)
+            .with_risk(CallAt15)
+            .spawn();
+        let events: Vec<_> = handle.rx_evt.iter().collect();
+        let call = events.iter().position(|e| matches!(e, EngineEvent::MarginCall(_))).unwrap();
+        let after = &events[call..];
+        let step_end = after.iter().position(|e| matches!(e, EngineEvent::Portfolio(_))).unwrap();
+
+        let trades: Vec<_> = after[..step_end]
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::Trade(t) => Some((t.symbol.as_str(), t.side, t.price)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(trades.len(), 2);
+        assert!(trades.iter().all(|(_, side, _)| matches!(side, Side::Sell)));
+        // BBB has no bar at minute 15 and goes at its last close
+        assert!(trades.contains(&("BBB", Side::Sell, 61.0)));
+        let EngineEvent::Portfolio(portfolio) = &after[step_end] else { unreachable!() };
+        assert!(portfolio.positions.is_empty());
+        // AAA is bought again on the next step
+        let bought = |e: &EngineEvent| matches!(e, EngineEvent::Trade(t) if t.side == Side::Buy);
+        assert!(after[step_end..].iter().any(bought));
+    }
+
+    #[test]
+    fn forward_tests_wait_on_their_feed_and_trade_each_step_as_it_arrives() {
+        let (tx, feed) = feed_channel();
+        let handle = Engine::builder(config(serde_json::json!({ "mode": "Forwardtest" })))
+            .with_data(Staggered)
+            .with_strategy(AlwaysLong)
+            .with_feed(feed)
+            .spawn();
+        let mut next = || handle.rx_evt.recv_timeout(Duration::from_secs(30)).unwrap();
+        let bars_until = |next: &mut dyn FnMut() -> EngineEvent, stop: fn(&EngineEvent) -> bool| {
+            let mut bars = Vec::new();
+            loop {
+                match next() {
+                    EngineEvent::Bar(b) => bars.push((b.symbol, b.ts)),
+                    evt if stop(&evt) => return (bars, evt),
+                    _ => {}
+                }
+            }
+        };
+
+        tx.send(vec![bar("AAA", 20, 101.0)]).unwrap();
+        let (bars, _) = bars_until(&mut next, |e| matches!(e, EngineEvent::Trade(_)));
+        assert!(bars.is_empty());
+        let (bars, _) = bars_until(&mut next, |e| matches!(e, EngineEvent::Metrics(_)));
+        assert_eq!(bars, [("AAA".to_string(), bar("AAA", 20, 0.0).ts)]);
+
+        // Nothing more has arrived, yet the run answers its controls
+        handle.tx_ctrl.send(EngineControl::Checkpoint).unwrap();
+        let is_checkpoint = |e: &EngineEvent| matches!(e, EngineEvent::Checkpoint(_));
+        let (bars, checkpoint) = bars_until(&mut next, is_checkpoint);
+        assert!(bars.is_empty());
+        let EngineEvent::Checkpoint(checkpoint) = checkpoint else { unreachable!() };
+        assert_eq!(checkpoint.cursor, 1);
+
+        // A step already traded is skipped
+        tx.send(vec![bar("AAA", 20, 101.0)]).unwrap();
+        tx.send(vec![bar("AAA", 21, 102.0), bar("BBB", 21, 60.0)]).unwrap();
+        let (bars, _) = bars_until(&mut next, |e| matches!(e, EngineEvent::Portfolio(_)));
+        let ts = bar("AAA", 21, 0.0).ts;
+        assert_eq!(bars, [("AAA".to_string(), ts), ("BBB".to_string(), ts)]);
+
+        handle.tx_ctrl.send(EngineControl::Stop).unwrap();
+        let is_done = |e: &EngineEvent| matches!(e, EngineEvent::Done(_));
+        let (bars, done) = bars_until(&mut next, is_done);
+        assert!(bars.is_empty());
+        assert!(matches!(done, EngineEvent::Done(RunStatus::Stopped)));
+    }
+
+    #[test]
+    fn forward_tests_on_loaded_history_need_a_feed() {
+        let source = serde_json::json!({ "Csv": { "paths": ["bars.csv"] } });
+        let config = config(serde_json::json!({ "mode": "Forwardtest", "data_source": source }));
+        let events: Vec<_> = Engine::spawn(config).rx_evt.iter().collect();
+        assert!(matches!(events[0], EngineEvent::Error(EngineError::Data(_))));
+        assert!(matches!(events[1], EngineEvent::Done(RunStatus::Failed)));
+    }
+
+    /// AAA only: ten flat training bars, then the given test bars.
+    struct History(Vec<Bar>);
+
+    impl DataSource for History {
+        fn load(&mut self, _symbols: &[String]) -> anyhow::Result<Vec<Vec<Bar>>> {
+            let train = (0..10).map(|m| bar("AAA", m, 100.0));
+            Ok(vec![train.chain(self.0.clone()).collect()])
+        }
+    }
+
+    struct OneUnit;
+
+    impl RiskManager for OneUnit {
+        fn entry_qty(&self, _ctx: &SizingContext) -> f64 {
+            1.0
+        }
+
+        fn margin_call(&self, _portfolio: &Portfolio, _ts: DateTime<Utc>) -> Option<MarginCall> {
+            None
+        }
+    }
+
+    #[test]
+    fn entries_rest_as_configured_orders_until_they_fill_or_expire() {
+        let day = 24 * 60;
+        let mut gap = bar("AAA", day + 1, 98.0);
+        gap.high = 99.0;
+        gap.low = 97.0;
+        let config = config(serde_json::json!({
+            "train_split": 0.72,
+            "entry": { "order": { "Limit": { "offset_pct": 0.01 } }, "tif": "Day" },
+        }));
+        let handle = Engine::builder(config)
+            .with_data(History(vec![
+                bar("AAA", 10, 100.0),
+                bar("AAA", 11, 100.0),
+                bar("AAA", day, 100.0),
+                gap,
+            ]))
+            .with_strategy(AlwaysLong)
+            .with_risk(OneUnit)
+            .spawn();
+
+        let orders: Vec<_> = handle
+            .rx_evt
+            .iter()
+            .filter_map(|evt| match evt {
+                EngineEvent::Order(o) => Some(format!("order {} {:?} {:?}", o.id, o.ty, o.tif)),
+                EngineEvent::Cancel(c) => Some(format!("cancel {} {:?}", c.order_id, c.reason)),
+                EngineEvent::Trade(t) => {
+                    Some(format!("trade {} {} {:?}", t.order_id, t.price, t.liquidity))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            orders,
+            [
+                // Rests below the close, and holds off a second entry the next minute
+                "order 1 Limit { price: 99.0 } Day",
+                // Lapses with its session; the next entry opens through its price
+                "cancel 1 Expired",
+                "order 2 Limit { price: 99.0 } Day",
+                "trade 2 98 Maker",
+            ]
+        );
+    }
 
-        EngineHandle { tx_ctrl, rx_evt }
+    #[test]
+    fn regime_labels_reach_events_but_not_the_strategy() {
+        let regime = |name: &str, drift: f64| {
+            serde_json::json!({ "name": name, "drift": drift, "volatility": 0.3 })
+        };
+        let config = config(serde_json::json!({
+            "price_model": { "RegimeSwitching": {
+                "regimes": [regime("bull", 0.5), regime("bear", -0.5)],
+                "transitions": [[0.9, 0.1], [0.1, 0.9]],
+            }},
+        }));
+        let peeking = Peeking::default();
+        let handle = Engine::builder(config).with_strategy(peeking.clone()).spawn();
+        let labelled = handle
+            .rx_evt
+            .iter()
+            .filter(|evt| matches!(evt, EngineEvent::Bar(b) if b.regime.is_some()))
+            .count();
+        assert_eq!(labelled, 300);
+        assert!(!peeking.0.load(Ordering::Relaxed));
     }
 }
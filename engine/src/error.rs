@@ -0,0 +1,51 @@
+/// Why a run ended with `RunStatus::Failed`, sent as `EngineEvent::Error`.
+#[derive(Clone, Debug, PartialEq, thiserror::Error, serde::Serialize, serde::Deserialize)]
+pub enum EngineError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("data source failed: {0}")]
+    Data(String),
+    #[error("strategy training failed: {0}")]
+    Training(String),
+    #[error("checkpoint could not be restored: {0}")]
+    Checkpoint(String),
+    #[error("journal failed: {0}")]
+    Journal(String),
+    #[error("export failed: {0}")]
+    Export(String),
+    #[error("engine panicked: {0}")]
+    Panicked(String),
+}
+
+impl EngineError {
+    /// Turns a payload caught by `catch_unwind` into an error with its message.
+    pub fn from_panic(payload: Box<dyn std::any::Any + Send>) -> Self {
+        let msg = match payload.downcast::<String>() {
+            Ok(s) => *s,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(s) => s.to_string(),
+                Err(_) => "unknown panic".to_string(),
+            },
+        };
+        Self::Panicked(msg)
+    }
+}
+
+/// A config field that failed validation, named by its path (`assets[0].tick_size`).
+#[derive(Clone, Debug, PartialEq, thiserror::Error, serde::Serialize, serde::Deserialize)]
+#[error("{field}: {message}")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Every problem `EngineConfig::validate` found.
+#[derive(Clone, Debug, PartialEq, thiserror::Error, serde::Serialize, serde::Deserialize)]
+#[error("invalid config: {}", join(.errors))]
+pub struct ConfigError {
+    pub errors: Vec<FieldError>,
+}
+
+fn join(errors: &[FieldError]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ")
+}
@@ -0,0 +1,537 @@
+use crate::types::*;
+use chrono::{DateTime, Utc};
+
+/// Outcome of submitting orders or matching them against a bar.
+#[derive(Clone, Debug)]
+pub enum Execution {
+    /// An order was accepted: either just submitted or a bracket leg placed after its entry filled.
+    Accepted(Order),
+    Fill(Trade),
+    Cancelled(Cancel),
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+struct Resting {
+    order: Order,
+    placed: DateTime<Utc>,
+    filled: f64,
+    /// The stop has traded: a stop works its remainder as a market order and a stop-limit
+    /// as a plain limit.
+    triggered: bool,
+}
+
+/// Turns orders into fills. The engine submits orders at a bar's close, lets resting
+/// orders trade against every new bar, and cancels a symbol's orders before exiting it.
+pub trait ExecutionModel: Send {
+    fn submit(&mut self, order: Order, bar: &Bar) -> Vec<Execution>;
+    fn on_bar(&mut self, bar: &Bar) -> Vec<Execution>;
+    fn cancel_symbol(&mut self, symbol: &str, ts: DateTime<Utc>) -> Vec<Execution>;
+    fn open_orders(&self) -> Vec<&Order>;
+
+    /// Working orders and counters to store in a checkpoint.
+    fn state(&self) -> anyhow::Result<serde_json::Value> {
+        Ok(serde_json::Value::Null)
+    }
+
+    /// Restores what `state` returned.
+    fn restore(&mut self, _state: serde_json::Value) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Simulated broker: assigns order ids, fills market orders at the bar close and keeps
+/// limit, stop and stop-limit orders resting until a later bar's OHLC reaches them.
+/// Taker fills pay the configured slippage, and a participation cap turns large orders
+/// into partial fills whose remainder keeps working on later bars.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SimBroker {
+    next_id: u64,
+    resting: Vec<Resting>,
+    slippage: SlippageModel,
+    max_participation: Option<f64>,
+    assets: Vec<Asset>,
+}
+
+impl Default for SimBroker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimBroker {
+    pub fn new() -> Self {
+        Self {
+            next_id: 1,
+            resting: Vec::new(),
+            slippage: SlippageModel::None,
+            max_participation: None,
+            assets: Vec::new(),
+        }
+    }
+
+    pub fn with_slippage(mut self, slippage: SlippageModel) -> Self {
+        self.slippage = slippage;
+        self
+    }
+
+    /// Caps each fill at `fraction` of the bar's volume.
+    pub fn with_max_participation(mut self, fraction: Option<f64>) -> Self {
+        self.max_participation = fraction;
+        self
+    }
+
+    /// Asset specs used for tick sizes; unknown symbols use a 0.01 tick.
+    pub fn with_assets(mut self, assets: Vec<Asset>) -> Self {
+        self.assets = assets;
+        self
+    }
+
+    fn group_of(&self, id: u64) -> Option<u64> {
+        self.resting.iter().find(|r| r.order.id == id).and_then(|r| r.order.oco_group)
+    }
+
+    fn fill(
+        &mut self,
+        mut r: Resting,
+        price: f64,
+        liquidity: Liquidity,
+        bar: &Bar,
+        out: &mut Vec<Execution>,
+    ) {
+        let remaining = r.order.qty - r.filled;
+        let qty = match self.max_participation {
+            Some(fraction) => remaining.min(fraction * bar.volume).max(0.0),
+            None => remaining,
+        };
+
+        if qty > 0.0 {
+            let price = match liquidity {
+                Liquidity::Taker => self.slipped(price, qty, &r.order, bar),
+                Liquidity::Maker => price,
+            };
+            r.filled += qty;
+            if matches!(r.order.ty, OrderType::Stop { .. }) {
+                r.triggered = true;
+            }
+            out.push(Execution::Fill(Trade {
+                order_id: r.order.id,
+                side: r.order.side,
+                price,
+                qty,
+                symbol: r.order.symbol.clone(),
+                ts: bar.ts,
+                fee: 0.0,
+                liquidity,
+            }));
+        }
+        let complete = r.order.qty - r.filled <= r.order.qty * 1e-9;
+
+        if let (Some(group), true) = (r.order.oco_group, qty > 0.0) {
+            let id = r.order.id;
+            if complete {
+                let (siblings, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.resting)
+                    .into_iter()
+                    .partition(|s| s.order.oco_group == Some(group));
+                self.resting = kept;
+                for s in siblings {
+                    out.push(cancel(&s.order, CancelReason::Oco, bar.ts));
+                }
+            } else {
+                // Siblings only need to cover what this leg has not closed yet
+                for s in self.resting.iter_mut().filter(|s| s.order.oco_group == Some(group)) {
+                    if s.order.id != id {
+                        s.order.qty -= qty;
+                    }
+                }
+            }
+        }
+
+        if let (Some(bracket), true) = (&r.order.bracket, qty > 0.0) {
+            let parent = r.order.id;
+            let mut legs = self.resting.iter_mut().filter(|l| l.order.parent_id == Some(parent));
+            match legs.next() {
+                // Later partial fills of the entry grow the legs already placed
+                Some(first) => {
+                    first.order.qty += qty;
+                    for leg in legs {
+                        leg.order.qty += qty;
+                    }
+                }
+                None => {
+                    let exit = r.order.side.opposite();
+                    let types = [
+                        OrderType::Limit { price: bracket.take_profit },
+                        OrderType::Stop { price: bracket.stop_loss },
+                    ];
+                    for ty in types {
+                        let mut leg = Order::new(&r.order.symbol, exit, qty, ty);
+                        leg.id = self.next_id;
+                        self.next_id += 1;
+                        leg.parent_id = Some(parent);
+                        leg.oco_group = Some(parent);
+                        out.push(Execution::Accepted(leg.clone()));
+                        self.resting.push(Resting {
+                            order: leg,
+                            placed: bar.ts,
+                            filled: 0.0,
+                            triggered: false,
+                        });
+                    }
+                }
+            }
+        }
+
+        if !complete {
+            if r.order.tif == TimeInForce::Ioc {
+                out.push(cancel(&r.order, CancelReason::Unfilled, bar.ts));
+            } else {
+                self.resting.push(r);
+            }
+        }
+    }
+
+    /// Moves a taker fill price against the order by the configured slippage.
+    fn slipped(&self, price: f64, qty: f64, order: &Order, bar: &Bar) -> f64 {
+        let slip = match &self.slippage {
+            SlippageModel::None => 0.0,
+            SlippageModel::FixedTicks { ticks } => {
+                let tick = self
+                    .assets
+                    .iter()
+                    .find(|a| a.symbol == order.symbol)
+                    .map(|a| a.tick_size)
+                    .unwrap_or(0.01);
+                ticks * tick
+            }
+            SlippageModel::RangeFraction { fraction } => fraction * (bar.high - bar.low),
+            SlippageModel::SqrtImpact { coefficient } => {
+                // The bar range stands in for volatility in price units
+                let participation = if bar.volume > 0.0 { qty / bar.volume } else { 1.0 };
+                coefficient * (bar.high - bar.low) * participation.sqrt()
+            }
+        };
+        match order.side {
+            Side::Buy => price + slip,
+            Side::Sell => (price - slip).max(0.0),
+        }
+    }
+}
+
+impl ExecutionModel for SimBroker {
+    /// Submits `order` at the close of `bar` and returns what happened to it. Orders
+    /// that are marketable at the close fill right away; the rest rest from the next bar.
+    fn submit(&mut self, mut order: Order, bar: &Bar) -> Vec<Execution> {
+        order.id = self.next_id;
+        self.next_id += 1;
+
+        let mut out = vec![Execution::Accepted(order.clone())];
+        let mut resting = Resting { order, placed: bar.ts, filled: 0.0, triggered: false };
+
+        if let Some(price) = immediate_price(&mut resting, bar.close) {
+            self.fill(resting, price, Liquidity::Taker, bar, &mut out);
+        } else if resting.order.tif == TimeInForce::Ioc {
+            out.push(cancel(&resting.order, CancelReason::Unfilled, bar.ts));
+        } else {
+            self.resting.push(resting);
+        }
+        out
+    }
+
+    /// Matches resting orders for `bar.symbol` against the bar. DAY orders from an
+    /// earlier session expire first. When both legs of an OCO pair trade within the
+    /// same bar the stop is assumed to have hit first.
+    fn on_bar(&mut self, bar: &Bar) -> Vec<Execution> {
+        let mut out = Vec::new();
+
+        let day = bar.ts.date_naive();
+        let (expired, kept): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.resting).into_iter().partition(|r| {
+                r.order.symbol == bar.symbol
+                    && r.order.tif == TimeInForce::Day
+                    && r.placed.date_naive() < day
+            });
+        self.resting = kept;
+        for r in expired {
+            out.push(cancel(&r.order, CancelReason::Expired, bar.ts));
+        }
+
+        let mut hits: Vec<(u64, f64, bool)> = Vec::new();
+        for r in self.resting.iter_mut().filter(|r| r.order.symbol == bar.symbol) {
+            if let Some(price) = match_bar(r, bar) {
+                let is_stop =
+                    matches!(r.order.ty, OrderType::Stop { .. } | OrderType::StopLimit { .. });
+                hits.push((r.order.id, price, is_stop));
+            }
+        }
+
+        // Keep one hit per OCO group, preferring the stop
+        let mut chosen: Vec<(u64, f64)> = Vec::new();
+        for &(id, price, is_stop) in &hits {
+            let group = self.group_of(id);
+            let beaten = group.is_some()
+                && hits.iter().any(|&(other, _, other_stop)| {
+                    other != id
+                        && self.group_of(other) == group
+                        && (other_stop && !is_stop || other_stop == is_stop && other < id)
+                });
+            if !beaten {
+                chosen.push((id, price));
+            }
+        }
+
+        for (id, price) in chosen {
+            // An earlier fill in this bar may already have cancelled it
+            if let Some(idx) = self.resting.iter().position(|r| r.order.id == id) {
+                let r = self.resting.remove(idx);
+                // Resting limits add liquidity; stops and market orders take it
+                let liquidity = match r.order.ty {
+                    OrderType::Limit { .. } => Liquidity::Maker,
+                    OrderType::StopLimit { .. } if r.triggered => Liquidity::Maker,
+                    _ => Liquidity::Taker,
+                };
+                self.fill(r, price, liquidity, bar, &mut out);
+            }
+        }
+        out
+    }
+
+    /// Cancels every resting order for `symbol`.
+    fn cancel_symbol(&mut self, symbol: &str, ts: DateTime<Utc>) -> Vec<Execution> {
+        let (cancelled, kept): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.resting).into_iter().partition(|r| r.order.symbol == symbol);
+        self.resting = kept;
+        cancelled.iter().map(|r| cancel(&r.order, CancelReason::Requested, ts)).collect()
+    }
+
+    fn open_orders(&self) -> Vec<&Order> {
+        self.resting.iter().map(|r| &r.order).collect()
+    }
+
+    fn state(&self) -> anyhow::Result<serde_json::Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> anyhow::Result<()> {
+        *self = serde_json::from_value(state)?;
+        Ok(())
+    }
+}
+
+fn cancel(order: &Order, reason: CancelReason, ts: DateTime<Utc>) -> Execution {
+    Execution::Cancelled(Cancel { order_id: order.id, symbol: order.symbol.clone(), reason, ts })
+}
+
+/// Price at which an order submitted at `close` executes straight away, if it does.
+fn immediate_price(r: &mut Resting, close: f64) -> Option<f64> {
+    let buy = r.order.side == Side::Buy;
+    match r.order.ty {
+        OrderType::Market => Some(close),
+        OrderType::Limit { price } => marketable(buy, price, close).then_some(close),
+        OrderType::Stop { price } => stop_hit(buy, price, close).then_some(close),
+        OrderType::StopLimit { stop, limit } => {
+            if !stop_hit(buy, stop, close) {
+                return None;
+            }
+            r.triggered = true;
+            marketable(buy, limit, close).then_some(close)
+        }
+    }
+}
+
+/// Fill price for a resting order against a bar's OHLC. A bar that opens through the
+/// order's price fills at the open (gap); otherwise touching the price fills at it.
+fn match_bar(r: &mut Resting, bar: &Bar) -> Option<f64> {
+    let buy = r.order.side == Side::Buy;
+    match r.order.ty {
+        OrderType::Market => Some(bar.open),
+        OrderType::Limit { price } => match_limit(buy, price, bar),
+        OrderType::Stop { .. } if r.triggered => Some(bar.open),
+        OrderType::Stop { price } => match_stop(buy, price, bar),
+        OrderType::StopLimit { stop, limit } => {
+            if r.triggered {
+                return match_limit(buy, limit, bar);
+            }
+            let trigger = match_stop(buy, stop, bar)?;
+            r.triggered = true;
+            if marketable(buy, limit, trigger) {
+                Some(trigger)
+            } else {
+                // Triggered away from the limit; it may still trade back to it within the bar
+                let reached = if buy { bar.low <= limit } else { bar.high >= limit };
+                reached.then_some(limit)
+            }
+        }
+    }
+}
+
+fn marketable(buy: bool, limit: f64, price: f64) -> bool {
+    if buy {
+        price <= limit
+    } else {
+        price >= limit
+    }
+}
+
+fn stop_hit(buy: bool, stop: f64, price: f64) -> bool {
+    if buy {
+        price >= stop
+    } else {
+        price <= stop
+    }
+}
+
+fn match_limit(buy: bool, limit: f64, bar: &Bar) -> Option<f64> {
+    if marketable(buy, limit, bar.open) {
+        Some(bar.open)
+    } else if (buy && bar.low <= limit) || (!buy && bar.high >= limit) {
+        Some(limit)
+    } else {
+        None
+    }
+}
+
+fn match_stop(buy: bool, stop: f64, bar: &Bar) -> Option<f64> {
+    if stop_hit(buy, stop, bar.open) {
+        Some(bar.open)
+    } else if (buy && bar.high >= stop) || (!buy && bar.low <= stop) {
+        Some(stop)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bar `hour` hours into 2024-01-02 (UTC).
+    fn bar(hour: i64, open: f64, high: f64, low: f64, close: f64) -> Bar {
+        let start: DateTime<Utc> = "2024-01-02T00:00:00Z".parse().unwrap();
+        Bar {
+            ts: start + chrono::Duration::hours(hour),
+            open,
+            high,
+            low,
+            close,
+            volume: 100.0,
+            symbol: "AAA".into(),
+            regime: None,
+        }
+    }
+
+    fn fills(execs: &[Execution]) -> Vec<(u64, f64, f64)> {
+        let fills = execs.iter().filter_map(|e| match e {
+            Execution::Fill(t) => Some((t.order_id, t.qty, t.price)),
+            _ => None,
+        });
+        fills.collect()
+    }
+
+    fn cancels(execs: &[Execution]) -> Vec<(u64, CancelReason)> {
+        let cancels = execs.iter().filter_map(|e| match e {
+            Execution::Cancelled(c) => Some((c.order_id, c.reason)),
+            _ => None,
+        });
+        cancels.collect()
+    }
+
+    fn limit(side: Side, price: f64) -> Order {
+        Order::new("AAA", side, 1.0, OrderType::Limit { price })
+    }
+
+    #[test]
+    fn orders_gapped_through_fill_at_the_open() {
+        let mut broker = SimBroker::new();
+        let placed = bar(0, 104.0, 106.0, 103.0, 105.0);
+        assert!(fills(&broker.submit(limit(Side::Buy, 100.0), &placed)).is_empty());
+        let stop = Order::new("AAA", Side::Sell, 1.0, OrderType::Stop { price: 95.0 });
+        assert!(fills(&broker.submit(stop, &placed)).is_empty());
+
+        let gap = bar(1, 90.0, 92.0, 88.0, 91.0);
+        assert_eq!(fills(&broker.on_bar(&gap)), [(1, 1.0, 90.0), (2, 1.0, 90.0)]);
+        // Touching without gapping fills at the order's own price
+        broker.submit(limit(Side::Buy, 89.0), &gap);
+        assert_eq!(fills(&broker.on_bar(&bar(2, 91.0, 92.0, 88.5, 90.0))), [(3, 1.0, 89.0)]);
+    }
+
+    #[test]
+    fn stop_limit_triggered_away_from_its_limit_waits_for_the_limit() {
+        let mut broker = SimBroker::new();
+        let ty = OrderType::StopLimit { stop: 105.0, limit: 106.0 };
+        broker.submit(Order::new("AAA", Side::Buy, 1.0, ty), &bar(0, 100.0, 101.0, 99.0, 100.0));
+
+        // Opens through the stop above the limit and never comes back to it
+        assert!(fills(&broker.on_bar(&bar(1, 108.0, 109.0, 107.0, 108.0))).is_empty());
+        // Triggered, it now rests as a plain limit and adds liquidity
+        let execs = broker.on_bar(&bar(2, 107.0, 107.5, 105.5, 106.5));
+        assert_eq!(fills(&execs), [(1, 1.0, 106.0)]);
+        assert!(matches!(&execs[0], Execution::Fill(t) if t.liquidity == Liquidity::Maker));
+    }
+
+    #[test]
+    fn oco_legs_hit_in_one_bar_assume_the_stop_first() {
+        let mut broker = SimBroker::new();
+        let entry = Order::market("AAA", Side::Buy, 1.0)
+            .with_bracket(Bracket { take_profit: 110.0, stop_loss: 95.0 });
+        let execs = broker.submit(entry, &bar(0, 100.0, 101.0, 99.0, 100.0));
+        assert_eq!(fills(&execs), [(1, 1.0, 100.0)]);
+        // Take-profit is order 2, stop-loss order 3
+        let execs = broker.on_bar(&bar(1, 100.0, 112.0, 94.0, 100.0));
+        assert_eq!(fills(&execs), [(3, 1.0, 95.0)]);
+        assert_eq!(cancels(&execs), [(2, CancelReason::Oco)]);
+        assert!(broker.open_orders().is_empty());
+    }
+
+    #[test]
+    fn capped_stops_fill_their_remainder_at_the_next_open() {
+        let mut broker = SimBroker::new().with_max_participation(Some(0.1));
+        let stop = Order::new("AAA", Side::Sell, 25.0, OrderType::Stop { price: 95.0 });
+        broker.submit(stop, &bar(0, 100.0, 101.0, 99.0, 100.0));
+
+        assert_eq!(fills(&broker.on_bar(&bar(1, 97.0, 98.0, 94.0, 96.0))), [(1, 10.0, 95.0)]);
+        // The stop has traded, so the rest no longer waits for the price to come back
+        assert_eq!(fills(&broker.on_bar(&bar(2, 100.0, 101.0, 99.0, 100.0))), [(1, 10.0, 100.0)]);
+        assert_eq!(fills(&broker.on_bar(&bar(3, 99.5, 101.0, 99.0, 100.0))), [(1, 5.0, 99.5)]);
+        assert!(broker.open_orders().is_empty());
+    }
+
+    #[test]
+    fn ioc_remainders_and_stale_day_orders_are_cancelled() {
+        let mut broker = SimBroker::new().with_max_participation(Some(0.1));
+        let placed = bar(0, 100.0, 101.0, 99.0, 100.0);
+
+        let execs = broker.submit(limit(Side::Buy, 90.0).with_tif(TimeInForce::Ioc), &placed);
+        assert_eq!(cancels(&execs), [(1, CancelReason::Unfilled)]);
+        let ioc = Order::market("AAA", Side::Buy, 25.0).with_tif(TimeInForce::Ioc);
+        let execs = broker.submit(ioc, &placed);
+        assert_eq!(fills(&execs), [(2, 10.0, 100.0)]);
+        assert_eq!(cancels(&execs), [(2, CancelReason::Unfilled)]);
+
+        broker.submit(limit(Side::Buy, 95.0).with_tif(TimeInForce::Day), &placed);
+        assert!(broker.on_bar(&bar(23, 100.0, 101.0, 99.0, 100.0)).is_empty());
+        // The next session expires it before it could trade
+        let execs = broker.on_bar(&bar(24, 96.0, 97.0, 90.0, 92.0));
+        assert!(fills(&execs).is_empty());
+        assert_eq!(cancels(&execs), [(3, CancelReason::Expired)]);
+    }
+
+    #[test]
+    fn partial_entry_fills_grow_the_bracket_legs() {
+        let mut broker = SimBroker::new().with_max_participation(Some(0.1));
+        let entry = Order::market("AAA", Side::Buy, 25.0)
+            .with_bracket(Bracket { take_profit: 110.0, stop_loss: 90.0 });
+        let flat = |hour| bar(hour, 100.0, 101.0, 99.0, 100.0);
+        let legs = |broker: &SimBroker| {
+            let legs = broker.open_orders().into_iter().filter(|o| o.parent_id == Some(1));
+            legs.map(|o| o.qty).collect::<Vec<_>>()
+        };
+
+        broker.submit(entry, &flat(0));
+        assert_eq!(legs(&broker), [10.0, 10.0]);
+        broker.on_bar(&flat(1));
+        assert_eq!(legs(&broker), [20.0, 20.0]);
+        broker.on_bar(&flat(2));
+        assert_eq!(legs(&broker), [25.0, 25.0]);
+        assert_eq!(broker.open_orders().len(), 2);
+    }
+}
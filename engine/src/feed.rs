@@ -0,0 +1,95 @@
+use crate::types::Bar;
+use chrono::Utc;
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, SendError, Sender};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// What a feed had for the run loop when asked for the next time step.
+#[derive(Clone, Debug)]
+pub enum FeedStep {
+    /// The bars of the next time step, at most one per symbol, all with the same `ts`.
+    Bars(Vec<Bar>),
+    /// Nothing arrived in time; the run loop handles its controls and asks again.
+    Waiting,
+    /// No more bars will come and the run completes.
+    Closed,
+}
+
+/// Delivers the time steps a run trades, one at a time. Backtests and simulations take
+/// theirs from steps loaded up front; `Mode::Forwardtest` waits on a feed for new bars.
+pub trait Feed: Send {
+    /// Waits at most `timeout` for the next time step.
+    fn next_step(&mut self, timeout: Duration) -> FeedStep;
+}
+
+/// Steps known up front. They are delivered straight away, or, with
+/// `released_at_close`, each once its bars have closed on the wall clock, which makes
+/// synthetic bars stand in for a live market.
+pub struct StepFeed {
+    steps: VecDeque<Vec<Bar>>,
+    release: Option<chrono::Duration>,
+}
+
+impl StepFeed {
+    pub fn new(steps: impl IntoIterator<Item = Vec<Bar>>) -> Self {
+        Self { steps: steps.into_iter().collect(), release: None }
+    }
+
+    /// Holds each step until `interval` after its timestamp has passed.
+    pub fn released_at_close(mut self, interval: Duration) -> Self {
+        self.release = Some(chrono::Duration::from_std(interval).unwrap_or_default());
+        self
+    }
+}
+
+impl Feed for StepFeed {
+    fn next_step(&mut self, timeout: Duration) -> FeedStep {
+        let Some(step) = self.steps.front() else {
+            return FeedStep::Closed;
+        };
+        if let Some(interval) = self.release {
+            let wait = (step[0].ts + interval - Utc::now()).to_std().unwrap_or_default();
+            if wait > timeout {
+                std::thread::sleep(timeout);
+                return FeedStep::Waiting;
+            }
+            std::thread::sleep(wait);
+        }
+        self.steps.pop_front().map_or(FeedStep::Closed, FeedStep::Bars)
+    }
+}
+
+/// Steps pushed from another thread, e.g. a market data client or the server's bar
+/// endpoint. The feed closes once every sender is dropped.
+pub struct ChannelFeed {
+    rx: Receiver<Vec<Bar>>,
+}
+
+/// Pushes steps into a `ChannelFeed`; clones push into the same feed.
+#[derive(Clone)]
+pub struct FeedSender {
+    tx: Sender<Vec<Bar>>,
+}
+
+impl FeedSender {
+    /// Queues the next step. Fails once the run reading the feed has ended.
+    pub fn send(&self, step: Vec<Bar>) -> Result<(), SendError<Vec<Bar>>> {
+        self.tx.send(step)
+    }
+}
+
+/// Creates a feed together with the sender that pushes its steps.
+pub fn feed_channel() -> (FeedSender, ChannelFeed) {
+    let (tx, rx) = unbounded();
+    (FeedSender { tx }, ChannelFeed { rx })
+}
+
+impl Feed for ChannelFeed {
+    fn next_step(&mut self, timeout: Duration) -> FeedStep {
+        match self.rx.recv_timeout(timeout) {
+            Ok(step) => FeedStep::Bars(step),
+            Err(RecvTimeoutError::Timeout) => FeedStep::Waiting,
+            Err(RecvTimeoutError::Disconnected) => FeedStep::Closed,
+        }
+    }
+}
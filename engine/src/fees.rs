@@ -0,0 +1,81 @@
+use crate::types::*;
+
+/// What an order has filled and been charged so far, so per-order charges apply once
+/// however many fills it takes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct OrderFees {
+    pub filled: f64,
+    pub charged: f64,
+}
+
+impl CommissionModel {
+    /// Commission for a fill of `qty` at `price` of an order with the given history.
+    /// `traded_volume` is the notional already traded in the run and selects the tier of a
+    /// tiered schedule.
+    pub fn fee(
+        &self,
+        qty: f64,
+        price: f64,
+        liquidity: Liquidity,
+        traded_volume: f64,
+        order: &OrderFees,
+    ) -> f64 {
+        let notional = qty.abs() * price;
+        match self {
+            CommissionModel::None => 0.0,
+            CommissionModel::Fixed { per_trade } if order.filled == 0.0 => *per_trade,
+            CommissionModel::Fixed { .. } => 0.0,
+            CommissionModel::PerShare { rate, min } => {
+                // The minimum is topped up on the first fill and absorbed by later ones
+                let total = ((order.filled + qty.abs()) * rate).max(*min);
+                (total - order.charged).max(0.0)
+            }
+            CommissionModel::Bps { bps } => notional * bps / 10_000.0,
+            CommissionModel::Tiered { tiers } => {
+                let tier = tiers
+                    .iter()
+                    .filter(|t| traded_volume >= t.min_volume)
+                    .max_by(|a, b| a.min_volume.total_cmp(&b.min_volume));
+                let bps = match (tier, liquidity) {
+                    (Some(t), Liquidity::Maker) => t.maker_bps,
+                    (Some(t), Liquidity::Taker) => t.taker_bps,
+                    (None, _) => 0.0,
+                };
+                notional * bps / 10_000.0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Total charged for one order filled in `fills`, booked as `Account` books them.
+    fn charge(model: &CommissionModel, fills: &[f64]) -> f64 {
+        let mut order = OrderFees::default();
+        for &qty in fills {
+            let fee = model.fee(qty, 10.0, Liquidity::Taker, 0.0, &order);
+            order.filled += qty;
+            order.charged += fee;
+        }
+        order.charged
+    }
+
+    #[test]
+    fn fixed_fee_is_charged_once_per_order() {
+        let model = CommissionModel::Fixed { per_trade: 1.5 };
+        assert_eq!(charge(&model, &[100.0]), 1.5);
+        assert_eq!(charge(&model, &[40.0, 30.0, 30.0]), 1.5);
+    }
+
+    #[test]
+    fn per_share_minimum_applies_to_the_whole_order() {
+        let model = CommissionModel::PerShare { rate: 0.01, min: 1.0 };
+        // 100 units come to the minimum however they are split
+        assert_eq!(charge(&model, &[100.0]), 1.0);
+        assert_eq!(charge(&model, &[40.0, 30.0, 30.0]), 1.0);
+        // Past the minimum every unit pays the rate
+        assert!((charge(&model, &[150.0, 150.0]) - 3.0).abs() < 1e-12);
+    }
+}
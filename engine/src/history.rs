@@ -0,0 +1,466 @@
+use crate::data::DataSource;
+use crate::types::*;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Malformed rows named in a load error; the rest are only counted.
+const MAX_REPORTED: usize = 10;
+
+/// Historical OHLCV bars from CSV files. Rows may come in any order and a symbol may be
+/// spread over several files; rows of symbols the run does not trade are ignored.
+pub struct CsvSource {
+    pub config: CsvConfig,
+}
+
+impl CsvSource {
+    pub fn new(config: CsvConfig) -> Self {
+        Self { config }
+    }
+
+    fn read_file(
+        &self,
+        path: &Path,
+        symbols: &[String],
+        timestamps: &TimestampParser,
+        rows: &mut Vec<Row>,
+        malformed: &mut Vec<String>,
+    ) -> anyhow::Result<()> {
+        let config = &self.config;
+        let delimiter = u8::try_from(config.delimiter)
+            .map_err(|_| anyhow::anyhow!("delimiter must be a single-byte character"))?;
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| anyhow::anyhow!("{}: no `{name}` column", path.display()))
+        };
+        let columns = [
+            column(&config.columns.timestamp)?,
+            column(&config.columns.open)?,
+            column(&config.columns.high)?,
+            column(&config.columns.low)?,
+            column(&config.columns.close)?,
+            column(&config.columns.volume)?,
+        ];
+
+        let symbol = if config.symbol_from_filename {
+            let stem = path.file_stem().and_then(|s| s.to_str());
+            let stem = stem.ok_or_else(|| anyhow::anyhow!("{}: no file name", path.display()))?;
+            SymbolOf::File(stem.to_string())
+        } else if let Some(i) = config.columns.symbol.as_deref().and_then(|c| column(c).ok()) {
+            SymbolOf::Column(i)
+        } else if let [only] = symbols {
+            SymbolOf::File(only.clone())
+        } else {
+            anyhow::bail!(
+                "{}: no symbol column; name one, set symbol_from_filename or trade a single symbol",
+                path.display()
+            );
+        };
+
+        for record in reader.records() {
+            let (line, record) = match record {
+                Ok(record) => (record.position().map(|p| p.line()).unwrap_or(0), record),
+                Err(e) => {
+                    let line = e.position().map(|p| p.line()).unwrap_or(0);
+                    malformed.push(format!("{}:{line}: {e}", path.display()));
+                    continue;
+                }
+            };
+            let symbol = match &symbol {
+                SymbolOf::File(symbol) => symbol.as_str(),
+                SymbolOf::Column(i) => record.get(*i).unwrap_or_default(),
+            };
+            if !symbols.iter().any(|s| s == symbol) {
+                continue;
+            }
+            let at = format!("{}:{line}", path.display());
+            match parse_row(&record, &columns, symbol, timestamps) {
+                Ok(bar) => rows.push(Row { bar, at }),
+                Err(e) => malformed.push(format!("{at}: {e}")),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DataSource for CsvSource {
+    fn load(&mut self, symbols: &[String]) -> anyhow::Result<Vec<Vec<Bar>>> {
+        let timestamps = TimestampParser::new(&self.config)?;
+        let mut rows = Vec::new();
+        let mut malformed = Vec::new();
+        for path in &self.config.paths {
+            self.read_file(Path::new(path), symbols, &timestamps, &mut rows, &mut malformed)?;
+        }
+
+        // Sorting is stable, so a duplicate is reported at its later position
+        rows.sort_by_key(|row| row.bar.ts);
+        let mut series: HashMap<String, Vec<Bar>> = HashMap::new();
+        for Row { bar, at } in rows {
+            let bars = series.entry(bar.symbol.clone()).or_default();
+            if bars.last().is_some_and(|last| last.ts == bar.ts) {
+                malformed.push(format!("{at}: second {} bar at {}", bar.symbol, bar.ts));
+                continue;
+            }
+            bars.push(bar);
+        }
+
+        if !malformed.is_empty() {
+            let mut report = malformed.iter().take(MAX_REPORTED).cloned().collect::<Vec<_>>();
+            if malformed.len() > MAX_REPORTED {
+                report.push(format!("and {} more", malformed.len() - MAX_REPORTED));
+            }
+            let report = report.join("; ");
+            if !self.config.skip_malformed {
+                anyhow::bail!("{} malformed CSV rows: {report}", malformed.len());
+            }
+            tracing::warn!("skipped {} malformed CSV rows: {report}", malformed.len());
+        }
+
+        symbols
+            .iter()
+            .map(|symbol| {
+                series
+                    .remove(symbol)
+                    .ok_or_else(|| anyhow::anyhow!("no CSV rows for symbol {symbol}"))
+            })
+            .collect()
+    }
+}
+
+struct Row {
+    bar: Bar,
+    at: String,
+}
+
+enum SymbolOf {
+    File(String),
+    Column(usize),
+}
+
+fn parse_row(
+    record: &csv::StringRecord,
+    columns: &[usize; 6],
+    symbol: &str,
+    timestamps: &TimestampParser,
+) -> Result<Bar, String> {
+    let field = |i: usize| record.get(columns[i]).ok_or("row is too short");
+    let number = |i: usize, name: &str| {
+        let value = field(i)?;
+        match value.parse::<f64>() {
+            Ok(x) if x.is_finite() => Ok(x),
+            _ => Err(format!("{name} `{value}` is not a number")),
+        }
+    };
+    let bar = Bar {
+        ts: timestamps.parse(field(0)?)?,
+        open: number(1, "open")?,
+        high: number(2, "high")?,
+        low: number(3, "low")?,
+        close: number(4, "close")?,
+        volume: number(5, "volume")?,
+        symbol: symbol.to_string(),
+        regime: None,
+    };
+    check_bar(&bar)?;
+    Ok(bar)
+}
+
+/// Rejects a bar no market could have printed. Loaders of other formats share these checks.
+pub(crate) fn check_bar(bar: &Bar) -> Result<(), String> {
+    if [bar.open, bar.high, bar.low, bar.close, bar.volume].iter().any(|x| !x.is_finite()) {
+        return Err("prices and volume must be numbers".into());
+    }
+    if bar.low <= 0.0 || bar.volume < 0.0 {
+        return Err("prices must be positive and volume must not be negative".into());
+    }
+    if bar.high < bar.open.max(bar.close) || bar.low > bar.open.min(bar.close) {
+        return Err("open and close must lie between low and high".into());
+    }
+    Ok(())
+}
+
+/// Turns the timestamp column into UTC, localizing times that carry no offset.
+struct TimestampParser {
+    format: Option<String>,
+    timezone: Tz,
+}
+
+impl TimestampParser {
+    fn new(config: &CsvConfig) -> anyhow::Result<Self> {
+        let timezone = match &config.timezone {
+            Some(name) => name.parse().map_err(|_| anyhow::anyhow!("unknown timezone {name}"))?,
+            None => Tz::UTC,
+        };
+        Ok(Self { format: config.timestamp_format.clone(), timezone })
+    }
+
+    fn parse(&self, value: &str) -> Result<DateTime<Utc>, String> {
+        let parsed = match self.format.as_deref() {
+            Some("unix") => value.parse().ok().and_then(|s| DateTime::from_timestamp(s, 0)),
+            Some("unix_ms") => value.parse().ok().and_then(DateTime::from_timestamp_millis),
+            Some(format) => match DateTime::parse_from_str(value, format) {
+                Ok(ts) => Some(ts.to_utc()),
+                Err(_) => NaiveDateTime::parse_from_str(value, format)
+                    .or_else(|_| {
+                        NaiveDate::parse_from_str(value, format)
+                            .map(|d| d.and_time(Default::default()))
+                    })
+                    .ok()
+                    .map(|naive| self.localize(naive))
+                    .transpose()?,
+            },
+            None => match DateTime::parse_from_rfc3339(value) {
+                Ok(ts) => Some(ts.to_utc()),
+                Err(_) => ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+                    .iter()
+                    .find_map(|f| NaiveDateTime::parse_from_str(value, f).ok())
+                    .or_else(|| {
+                        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                            .ok()
+                            .map(|d| d.and_time(Default::default()))
+                    })
+                    .map(|naive| self.localize(naive))
+                    .transpose()?,
+            },
+        };
+        parsed.ok_or_else(|| format!("timestamp `{value}` cannot be parsed"))
+    }
+
+    fn localize(&self, naive: NaiveDateTime) -> Result<DateTime<Utc>, String> {
+        // Ambiguous times at the end of daylight saving resolve to the first occurrence
+        self.timezone
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|ts| ts.to_utc())
+            .ok_or_else(|| format!("{naive} does not exist in {}", self.timezone))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh directory per test, so tests running in parallel keep their files apart.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nexow-csv-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, contents: &str) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Loads `symbols` from `paths`; `extra` sets further `CsvConfig` fields.
+    fn load(
+        paths: &[String],
+        symbols: &[&str],
+        extra: serde_json::Value,
+    ) -> anyhow::Result<Vec<Vec<Bar>>> {
+        let mut config = serde_json::json!({ "paths": paths });
+        for (key, value) in extra.as_object().unwrap() {
+            config[key] = value.clone();
+        }
+        let symbols: Vec<String> = symbols.iter().map(|s| s.to_string()).collect();
+        CsvSource::new(serde_json::from_value(config).unwrap()).load(&symbols)
+    }
+
+    fn ts(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn columns_are_found_by_their_configured_names() {
+        let dir = temp_dir("columns");
+        let file = write(
+            &dir,
+            "bars.csv",
+            "Vol;Ticker;Note;Time;O;H;L;C\n\
+             20;BBB;x;2024-01-02T00:01:00Z;50;51;49;50.5\n\
+             10;AAA;x;2024-01-02T00:01:00Z;100;102;99;101\n\
+             30;CCC;x;not even read;;;;\n\
+             15;AAA;x;2024-01-02T00:00:00Z;99;101;98;100\n",
+        );
+        let columns = serde_json::json!({
+            "timestamp": "Time", "open": "O", "high": "H", "low": "L", "close": "C",
+            "volume": "Vol", "symbol": "Ticker",
+        });
+        let extra = serde_json::json!({ "columns": columns, "delimiter": ';' });
+        let series = load(&[file], &["AAA", "BBB"], extra).unwrap();
+
+        // In time order per symbol; CCC is not traded, so its broken row is ignored
+        let aaa: Vec<_> =
+            series[0].iter().map(|b| (b.ts, b.open, b.high, b.low, b.close)).collect();
+        assert_eq!(
+            aaa,
+            [
+                (ts("2024-01-02T00:00:00Z"), 99.0, 101.0, 98.0, 100.0),
+                (ts("2024-01-02T00:01:00Z"), 100.0, 102.0, 99.0, 101.0),
+            ]
+        );
+        assert_eq!(series[0][1].volume, 10.0);
+        assert_eq!(series[1].len(), 1);
+        assert_eq!(series[1][0].symbol, "BBB");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn timestamps_without_an_offset_are_localized() {
+        let dir = temp_dir("timezone");
+        let rows = |stamps: &[&str]| {
+            let rows: String = stamps.iter().map(|s| format!("{s},10,11,9,10,5\n")).collect();
+            format!("timestamp,open,high,low,close,volume\n{rows}")
+        };
+        let file = write(
+            &dir,
+            "ny.csv",
+            &rows(&[
+                "2024-01-02 09:30",
+                // Daylight saving time; an offset in the value wins over the zone
+                "2024-07-01 09:30",
+                // Clocks go back: the first 01:30 is taken
+                "2024-11-03 01:30",
+            ]),
+        );
+        let zone = serde_json::json!({
+            "timestamp_format": "%Y-%m-%d %H:%M",
+            "timezone": "America/New_York",
+        });
+        let series = load(&[file], &["AAA"], zone.clone()).unwrap();
+        let stamps: Vec<_> = series[0].iter().map(|b| b.ts).collect();
+        assert_eq!(
+            stamps,
+            [ts("2024-01-02T14:30:00Z"), ts("2024-07-01T13:30:00Z"), ts("2024-11-03T05:30:00Z"),]
+        );
+
+        // Clocks go forward: 02:30 never happened in New York that night
+        let gap = write(&dir, "gap.csv", &rows(&["2024-03-10 01:30", "2024-03-10 02:30"]));
+        let err = load(&[gap], &["AAA"], zone).unwrap_err().to_string();
+        assert!(
+            err.contains("gap.csv:3: 2024-03-10 02:30:00 does not exist in America/New_York"),
+            "{err}"
+        );
+
+        let offsets = write(&dir, "offsets.csv", &rows(&["2024-01-02T09:30:00-05:00"]));
+        let series = load(&[offsets], &["AAA"], serde_json::json!({ "timezone": "Asia/Tokyo" }));
+        assert_eq!(series.unwrap()[0][0].ts, ts("2024-01-02T14:30:00Z"));
+
+        let unix = write(&dir, "unix.csv", &rows(&["1704205800"]));
+        let series = load(&[unix], &["AAA"], serde_json::json!({ "timestamp_format": "unix" }));
+        assert_eq!(series.unwrap()[0][0].ts, ts("2024-01-02T14:30:00Z"));
+        let unix_ms = write(&dir, "unix_ms.csv", &rows(&["1704205800250"]));
+        let format = serde_json::json!({ "timestamp_format": "unix_ms" });
+        assert_eq!(
+            load(&[unix_ms], &["AAA"], format).unwrap()[0][0].ts,
+            ts("2024-01-02T14:30:00.250Z")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn symbols_can_come_from_file_names() {
+        let dir = temp_dir("filenames");
+        let header = "timestamp,open,high,low,close,volume\n";
+        let aaa = write(&dir, "AAA.csv", &format!("{header}2024-01-02T00:00:00Z,10,11,9,10,5\n"));
+        let bbb = write(&dir, "BBB.csv", &format!("{header}2024-01-02T00:00:00Z,20,21,19,20,5\n"));
+        let extra = serde_json::json!({ "symbol_from_filename": true });
+        let series = load(&[aaa.clone(), bbb], &["BBB", "AAA"], extra).unwrap();
+        assert_eq!((series[0][0].symbol.as_str(), series[0][0].close), ("BBB", 20.0));
+        assert_eq!((series[1][0].symbol.as_str(), series[1][0].close), ("AAA", 10.0));
+
+        // Without a symbol column a file holds the run's only symbol
+        let series = load(std::slice::from_ref(&aaa), &["XYZ"], serde_json::json!({})).unwrap();
+        assert_eq!(series[0][0].symbol, "XYZ");
+        let err = load(&[aaa], &["AAA", "BBB"], serde_json::json!({})).unwrap_err();
+        assert!(err.to_string().contains("no symbol column"), "{err}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn duplicate_timestamps_are_malformed() {
+        let dir = temp_dir("duplicates");
+        let header = "timestamp,symbol,open,high,low,close,volume\n";
+        let first =
+            write(&dir, "first.csv", &format!("{header}2024-01-02T00:00:00Z,AAA,10,11,9,10,5\n"));
+        let second = write(
+            &dir,
+            "second.csv",
+            &format!(
+                "{header}2024-01-02T00:01:00Z,AAA,12,13,11,12,5\n\
+                 2024-01-02T00:00:00Z,AAA,11,12,10,11,5\n\
+                 2024-01-02T00:00:00Z,BBB,11,12,10,11,5\n"
+            ),
+        );
+        let paths = [first, second];
+        let err = load(&paths, &["AAA", "BBB"], serde_json::json!({})).unwrap_err().to_string();
+        assert!(err.starts_with("1 malformed CSV rows"), "{err}");
+        assert!(err.contains("second.csv:3: second AAA bar at 2024-01-02 00:00:00 UTC"), "{err}");
+
+        // Skipped, the bar read first stays
+        let series = load(&paths, &["AAA", "BBB"], serde_json::json!({ "skip_malformed": true }));
+        let closes: Vec<_> = series.unwrap()[0].iter().map(|b| b.close).collect();
+        assert_eq!(closes, [10.0, 12.0]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_rows_are_reported_or_skipped() {
+        let dir = temp_dir("malformed");
+        let mut contents = String::from(
+            "timestamp,open,high,low,close,volume\n\
+             2024-01-02T00:00:00Z,10,11,9,10,5\n\
+             2024-01-02T00:01:00Z,10,11,9\n\
+             2024-01-02T00:02:00Z,ten,11,9,10,5\n\
+             2024-01-02T00:03:00Z,10,11,9,NaN,5\n\
+             2024-01-02T00:04:00Z,10,11,9,12,5\n\
+             2024-01-02T00:05:00Z,10,11,0,10,5\n\
+             2024-01-02T00:06:00Z,10,11,9,10,-5\n\
+             yesterday,10,11,9,10,5\n\
+             2024-01-02T00:08:00Z,10,11,9,10,5\n",
+        );
+        let file = write(&dir, "bars.csv", &contents);
+
+        let err = load(std::slice::from_ref(&file), &["AAA"], serde_json::json!({}))
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("7 malformed CSV rows: "), "{err}");
+        for expected in [
+            ":3: CSV error: record 2 (line: 3, byte: 71): found record with 4 fields",
+            ":4: open `ten` is not a number",
+            ":5: close `NaN` is not a number",
+            ":6: open and close must lie between low and high",
+            ":7: prices must be positive",
+            ":8: prices must be positive and volume must not be negative",
+            ":9: timestamp `yesterday` cannot be parsed",
+        ] {
+            assert!(err.contains(expected), "{expected} missing from {err}");
+        }
+
+        let series = load(&[file], &["AAA"], serde_json::json!({ "skip_malformed": true }));
+        let stamps: Vec<_> = series.unwrap()[0].iter().map(|b| b.ts).collect();
+        assert_eq!(stamps, [ts("2024-01-02T00:00:00Z"), ts("2024-01-02T00:08:00Z")]);
+
+        // Past ten, the rest are only counted
+        for minute in 10..20 {
+            contents.push_str(&format!("2024-01-02T00:{minute}:00Z,10,11,9,10,\n"));
+        }
+        let file = write(&dir, "many.csv", &contents);
+        let err = load(&[file], &["AAA"], serde_json::json!({})).unwrap_err().to_string();
+        assert!(err.starts_with("17 malformed CSV rows: "), "{err}");
+        assert!(err.ends_with("; and 7 more"), "{err}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
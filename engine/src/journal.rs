@@ -0,0 +1,183 @@
+use crate::engine::EngineEvent;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// One line of a journal: an event with its sequence number and the wall time at which
+/// the engine emitted it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub recorded_at: DateTime<Utc>,
+    pub event: EngineEvent,
+}
+
+#[derive(Serialize)]
+struct EntryRef<'a> {
+    seq: u64,
+    recorded_at: DateTime<Utc>,
+    event: &'a EngineEvent,
+}
+
+/// Writes events to a JSON-lines journal, one run per file.
+pub struct JournalWriter {
+    out: BufWriter<File>,
+    next_seq: u64,
+}
+
+impl JournalWriter {
+    /// Starts the journal of a new run. An existing file is refused rather than having a
+    /// second run spliced onto it.
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new().write(true).create_new(true).open(path).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::AlreadyExists {
+                anyhow::anyhow!("{} already exists; journals hold a single run", path.display())
+            } else {
+                anyhow::anyhow!("{}: {e}", path.display())
+            }
+        })?;
+        Ok(Self { out: BufWriter::new(file), next_seq: 0 })
+    }
+
+    /// Continues the journal of a run resumed from a checkpoint whose next entry is
+    /// `next_seq`. What the original run recorded after the checkpoint is cut off, along
+    /// with a last line torn by a crash, so the resumed run carries on from the checkpoint.
+    pub fn resume(path: impl AsRef<Path>, next_seq: u64) -> anyhow::Result<Self> {
+        #[derive(Deserialize)]
+        struct Seq {
+            seq: u64,
+        }
+
+        let path = path.as_ref();
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut reader = BufReader::new(&file);
+        let (mut kept, mut expected) = (0, 0);
+        let mut line = String::new();
+        while expected < next_seq {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            let entry = serde_json::from_str::<Seq>(&line).ok().filter(|_| line.ends_with('\n'));
+            match entry {
+                Some(entry) => expected = entry.seq + 1,
+                None if line.trim().is_empty() && read > 0 => {}
+                None => break,
+            }
+            kept += read as u64;
+        }
+        if expected != next_seq {
+            anyhow::bail!(
+                "{} holds entries up to {expected}, short of the checkpoint's {next_seq}",
+                path.display()
+            );
+        }
+        file.set_len(kept)?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self { out: BufWriter::new(file), next_seq })
+    }
+
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    pub fn append(&mut self, event: &EngineEvent) -> anyhow::Result<()> {
+        let entry = EntryRef { seq: self.next_seq, recorded_at: Utc::now(), event };
+        serde_json::to_writer(&mut self.out, &entry)?;
+        self.out.write_all(b"\n")?;
+        self.next_seq += 1;
+        // Flush at step boundaries so a crashed run leaves whole steps behind, and at
+        // checkpoints so a run resumed from one finds it recorded
+        if matches!(
+            event,
+            EngineEvent::Metrics(_) | EngineEvent::Checkpoint(_) | EngineEvent::Done(_)
+        ) {
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a journal back entry by entry; a malformed line yields an error naming it.
+pub struct JournalReader {
+    lines: std::io::Lines<BufReader<File>>,
+    line: usize,
+}
+
+impl JournalReader {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self { lines: BufReader::new(file).lines(), line: 0 })
+    }
+
+    /// Entries up to and including the next `Metrics` event, which closes every engine
+    /// step. Empty once the journal is exhausted.
+    pub fn next_step(&mut self) -> anyhow::Result<Vec<JournalEntry>> {
+        let mut step = Vec::new();
+        for entry in self.by_ref() {
+            let entry = entry?;
+            let end = matches!(entry.event, EngineEvent::Metrics(_));
+            step.push(entry);
+            if end {
+                break;
+            }
+        }
+        Ok(step)
+    }
+}
+
+impl Iterator for JournalReader {
+    type Item = anyhow::Result<JournalEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(
+                serde_json::from_str(&line)
+                    .map_err(|e| anyhow::anyhow!("journal line {}: {e}", self.line)),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::RunStatus;
+
+    fn seqs(path: &Path) -> Vec<u64> {
+        JournalReader::open(path).unwrap().map(|e| e.unwrap().seq).collect()
+    }
+
+    #[test]
+    fn new_runs_refuse_an_existing_journal_and_resumed_runs_cut_it_back() {
+        let path = std::env::temp_dir().join(format!("nexow-journal-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let done = EngineEvent::Done(RunStatus::Stopped);
+
+        let mut journal = JournalWriter::create(&path).unwrap();
+        for _ in 0..4 {
+            journal.append(&done).unwrap();
+        }
+        drop(journal);
+        assert!(JournalWriter::create(&path).is_err());
+        // A crash mid-write leaves a torn last line
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"seq":4,"recorded_at":"2024-"#).unwrap();
+        assert!(JournalWriter::resume(&path, 6).is_err());
+
+        let mut journal = JournalWriter::resume(&path, 2).unwrap();
+        journal.append(&done).unwrap();
+        drop(journal);
+        assert_eq!(seqs(&path), [0, 1, 2]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
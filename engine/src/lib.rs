@@ -1,9 +1,44 @@
 pub mod types;
+pub mod channel;
+pub mod checkpoint;
+pub mod clock;
+#[cfg(feature = "columnar")]
+pub mod columnar;
+pub mod config;
 pub mod data;
+pub mod error;
+pub mod execution;
+pub mod feed;
+pub mod fees;
+pub mod history;
+pub mod journal;
+pub mod margin;
+pub mod metrics;
+pub mod portfolio;
+pub mod report;
+pub mod risk;
+pub mod sizing;
 pub mod strategy;
+pub mod stream;
 pub mod engine;
 
 pub use types::*;
+pub use channel::*;
+pub use checkpoint::*;
+pub use clock::*;
+#[cfg(feature = "columnar")]
+pub use columnar::*;
 pub use data::*;
+pub use error::*;
+pub use execution::*;
+pub use feed::*;
+pub use fees::*;
+pub use history::*;
+pub use journal::*;
+pub use metrics::*;
+pub use report::*;
+pub use risk::*;
+pub use sizing::*;
 pub use strategy::*;
+pub use stream::*;
 pub use engine::*;
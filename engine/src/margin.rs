@@ -0,0 +1,21 @@
+use crate::types::*;
+
+impl MarginConfig {
+    /// Additional gross exposure the account can open under the initial requirement.
+    pub fn buying_power(&self, portfolio: &Portfolio) -> f64 {
+        let capacity =
+            if self.initial > 0.0 { portfolio.equity / self.initial } else { f64::INFINITY };
+        (capacity - portfolio.gross_exposure()).max(0.0)
+    }
+
+    /// Equity the open positions must be backed by before they are liquidated.
+    pub fn maintenance_requirement(&self, portfolio: &Portfolio) -> f64 {
+        portfolio.gross_exposure() * self.maintenance
+    }
+
+    /// Whether equity has fallen below the maintenance requirement.
+    pub fn is_breached(&self, portfolio: &Portfolio) -> bool {
+        portfolio.gross_exposure() > 0.0
+            && portfolio.equity < self.maintenance_requirement(portfolio)
+    }
+}
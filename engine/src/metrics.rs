@@ -0,0 +1,197 @@
+use crate::types::*;
+
+pub(crate) const MS_PER_YEAR: f64 = 365.25 * 24.0 * 3600.0 * 1000.0;
+
+/// Accumulates the per-step return series and closed-trade outcomes of a run and turns
+/// them into `Metrics`. Ratios are annualized from the bar interval, assuming markets
+/// that trade around the clock, with a zero risk-free rate. Ratios that are undefined
+/// (no variance, no losses, no drawdown) are reported as 0.0.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct MetricsTracker {
+    periods_per_year: f64,
+    starting_equity: f64,
+    last_equity: f64,
+    peak_equity: f64,
+    max_drawdown: f64,
+    drawdown_bars: usize,
+    max_drawdown_bars: usize,
+    pub returns: Vec<f64>,
+    sum: f64,
+    sum_sq: f64,
+    downside_sq: f64,
+    bars_in_market: usize,
+    wins: usize,
+    losses: usize,
+    gross_profit: f64,
+    gross_loss: f64,
+}
+
+impl MetricsTracker {
+    pub fn new(starting_equity: f64, bar_interval_ms: u64) -> Self {
+        Self {
+            periods_per_year: MS_PER_YEAR / bar_interval_ms.max(1) as f64,
+            starting_equity,
+            last_equity: starting_equity,
+            peak_equity: starting_equity,
+            max_drawdown: 0.0,
+            drawdown_bars: 0,
+            max_drawdown_bars: 0,
+            returns: Vec::new(),
+            sum: 0.0,
+            sum_sq: 0.0,
+            downside_sq: 0.0,
+            bars_in_market: 0,
+            wins: 0,
+            losses: 0,
+            gross_profit: 0.0,
+            gross_loss: 0.0,
+        }
+    }
+
+    /// Records the equity at the end of a time step and whether any position was open.
+    pub fn on_step(&mut self, equity: f64, invested: bool) {
+        let ret = if self.last_equity != 0.0 { equity / self.last_equity - 1.0 } else { 0.0 };
+        self.returns.push(ret);
+        self.sum += ret;
+        self.sum_sq += ret * ret;
+        if ret < 0.0 {
+            self.downside_sq += ret * ret;
+        }
+        self.last_equity = equity;
+        if invested {
+            self.bars_in_market += 1;
+        }
+
+        if equity >= self.peak_equity {
+            self.peak_equity = equity;
+            self.drawdown_bars = 0;
+        } else {
+            self.drawdown_bars += 1;
+            self.max_drawdown_bars = self.max_drawdown_bars.max(self.drawdown_bars);
+        }
+        self.max_drawdown = self.max_drawdown.max(self.drawdown());
+    }
+
+    /// Records the realized PnL of a fill that reduced or closed a position.
+    pub fn on_close(&mut self, realized: f64) {
+        if realized >= 0.0 {
+            self.wins += 1;
+            self.gross_profit += realized;
+        } else {
+            self.losses += 1;
+            self.gross_loss -= realized;
+        }
+    }
+
+    pub fn drawdown(&self) -> f64 {
+        (self.peak_equity - self.last_equity) / self.peak_equity.max(1.0)
+    }
+
+    pub fn snapshot(&self, pnl: f64, fees: f64) -> Metrics {
+        let n = self.returns.len() as f64;
+        let trades = self.wins + self.losses;
+        let (mean, std, downside) = if n > 0.0 {
+            let mean = self.sum / n;
+            let var = (self.sum_sq / n - mean * mean).max(0.0);
+            (mean, var.sqrt(), (self.downside_sq / n).sqrt())
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+        let ann = self.periods_per_year.sqrt();
+        let ratio = |num: f64, den: f64| if den > 0.0 { num / den } else { 0.0 };
+
+        let years = n / self.periods_per_year;
+        let growth = ratio(self.last_equity, self.starting_equity);
+        let cagr = if years > 0.0 && growth > 0.0 { growth.powf(1.0 / years) - 1.0 } else { 0.0 };
+        // Compounding a few seconds of bars over a year overflows; treat it as undefined
+        let cagr = if cagr.is_finite() { cagr } else { 0.0 };
+
+        Metrics {
+            pnl,
+            fees,
+            max_drawdown: self.max_drawdown,
+            sharpe: ratio(mean, std) * ann,
+            win_rate: ratio(self.wins as f64, trades as f64),
+            trades,
+            drawdown: self.drawdown(),
+            max_drawdown_bars: self.max_drawdown_bars,
+            sortino: ratio(mean, downside) * ann,
+            calmar: ratio(cagr, self.max_drawdown),
+            cagr,
+            volatility: std * ann,
+            profit_factor: ratio(self.gross_profit, self.gross_loss),
+            expectancy: ratio(self.gross_profit - self.gross_loss, trades as f64),
+            avg_win: ratio(self.gross_profit, self.wins as f64),
+            avg_loss: ratio(self.gross_loss, self.losses as f64),
+            time_in_market: ratio(self.bars_in_market as f64, n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, what: &str) {
+        assert!((actual - expected).abs() < 1e-9, "{what}: {actual} != {expected}");
+    }
+
+    #[test]
+    fn metrics_match_a_hand_computed_equity_series() {
+        // Four bars a year, so one year of steps and annualizing multiplies by 2
+        let mut tracker = MetricsTracker::new(100.0, (MS_PER_YEAR / 4.0) as u64);
+        // Returns +10%, -10%, 0, +10%, flat on the third step
+        for (equity, invested) in [(110.0, true), (99.0, true), (99.0, false), (108.9, true)] {
+            tracker.on_step(equity, invested);
+        }
+        for realized in [15.0, -5.0, 10.0] {
+            tracker.on_close(realized);
+        }
+        let m = tracker.snapshot(20.0, 1.5);
+
+        // mean 0.025, population variance 0.03 / 4 - 0.025², downside deviation √(0.01 / 4)
+        let std = (0.03f64 / 4.0 - 0.025 * 0.025).sqrt();
+        assert_close(m.sharpe, 0.025 / std * 2.0, "sharpe");
+        assert_close(m.sortino, 0.025 / 0.05 * 2.0, "sortino");
+        assert_close(m.volatility, std * 2.0, "volatility");
+        assert_close(m.cagr, 0.089, "cagr");
+        assert_close(m.max_drawdown, 0.1, "max_drawdown");
+        assert_close(m.calmar, 0.89, "calmar");
+        // Still 1.1 below the 110 peak, three bars after it
+        assert_close(m.drawdown, 0.01, "drawdown");
+        assert_eq!(m.max_drawdown_bars, 3);
+        assert_eq!(m.trades, 3);
+        assert_close(m.win_rate, 2.0 / 3.0, "win_rate");
+        assert_close(m.profit_factor, 25.0 / 5.0, "profit_factor");
+        assert_close(m.expectancy, 20.0 / 3.0, "expectancy");
+        assert_close(m.avg_win, 12.5, "avg_win");
+        assert_close(m.avg_loss, 5.0, "avg_loss");
+        assert_close(m.time_in_market, 0.75, "time_in_market");
+        assert_eq!((m.pnl, m.fees), (20.0, 1.5));
+    }
+
+    #[test]
+    fn undefined_ratios_are_zero() {
+        let m = MetricsTracker::new(100.0, 60_000).snapshot(0.0, 0.0);
+        let ratios = [m.sharpe, m.sortino, m.calmar, m.cagr, m.win_rate, m.profit_factor];
+        assert_eq!(ratios, [0.0; 6]);
+        assert_eq!((m.expectancy, m.avg_win, m.avg_loss, m.time_in_market), (0.0, 0.0, 0.0, 0.0));
+
+        // Flat equity has no variance and no drawdown; winners only leave no loss to divide by
+        let mut tracker = MetricsTracker::new(100.0, 60_000);
+        for _ in 0..3 {
+            tracker.on_step(100.0, true);
+        }
+        tracker.on_close(5.0);
+        let m = tracker.snapshot(5.0, 0.0);
+        assert_eq!([m.sharpe, m.sortino, m.calmar, m.profit_factor], [0.0; 4]);
+        assert_eq!(m.avg_loss, 0.0);
+        assert_eq!(m.win_rate, 1.0);
+
+        // A gain compounded over a year of millisecond bars overflows
+        let mut tracker = MetricsTracker::new(100.0, 1);
+        tracker.on_step(110.0, true);
+        let m = tracker.snapshot(0.0, 0.0);
+        assert_eq!((m.cagr, m.calmar), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::types::*;
+
+impl Portfolio {
+    pub fn new(cash: f64) -> Self {
+        Self { cash, positions: Vec::new(), equity: cash }
+    }
+
+    pub fn position(&self, symbol: &str) -> Option<&Position> {
+        self.positions.iter().find(|p| p.symbol == symbol)
+    }
+
+    /// Signed quantity held in `symbol` (0.0 when flat).
+    pub fn qty(&self, symbol: &str) -> f64 {
+        self.position(symbol).map(|p| p.qty).unwrap_or(0.0)
+    }
+
+    /// Books a fill against cash and the symbol's position and returns the realized PnL.
+    pub fn apply_fill(&mut self, symbol: &str, side: &Side, qty: f64, price: f64) -> f64 {
+        let signed = match side {
+            Side::Buy => qty,
+            Side::Sell => -qty,
+        };
+        self.cash -= signed * price;
+
+        let idx = match self.positions.iter().position(|p| p.symbol == symbol) {
+            Some(i) => i,
+            None => {
+                self.positions.push(Position {
+                    symbol: symbol.to_string(),
+                    qty: 0.0,
+                    avg_price: 0.0,
+                    unrealized_pnl: 0.0,
+                });
+                self.positions.len() - 1
+            }
+        };
+
+        let pos = &mut self.positions[idx];
+        let mut realized = 0.0;
+        if pos.qty == 0.0 || pos.qty.signum() == signed.signum() {
+            // opening or adding: blend the average price
+            let held = pos.qty.abs();
+            pos.avg_price = (pos.avg_price * held + price * qty) / (held + qty);
+            pos.qty += signed;
+        } else {
+            // reducing, closing or flipping
+            let closed = qty.min(pos.qty.abs());
+            realized = closed * (price - pos.avg_price) * pos.qty.signum();
+            let prev = pos.qty;
+            pos.qty += signed;
+            // Summed partial fills leave rounding dust; treat it as flat
+            if pos.qty.abs() <= qty * 1e-9 {
+                pos.qty = 0.0;
+            } else if pos.qty.signum() != prev.signum() {
+                pos.avg_price = price;
+            }
+        }
+        pos.unrealized_pnl = pos.qty * (price - pos.avg_price);
+
+        if pos.qty == 0.0 {
+            self.positions.remove(idx);
+        }
+        realized
+    }
+
+    /// Marks the symbol's position to `price`.
+    pub fn mark(&mut self, symbol: &str, price: f64) {
+        if let Some(pos) = self.positions.iter_mut().find(|p| p.symbol == symbol) {
+            pos.unrealized_pnl = pos.qty * (price - pos.avg_price);
+        }
+    }
+
+    /// Sum of absolute marked position values, longs and shorts alike.
+    pub fn gross_exposure(&self) -> f64 {
+        self.positions.iter().map(|p| (p.qty * p.avg_price + p.unrealized_pnl).abs()).sum()
+    }
+
+    /// Recomputes and returns total equity from cash and marked positions.
+    pub fn update_equity(&mut self) -> f64 {
+        let value: f64 =
+            self.positions.iter().map(|p| p.qty * p.avg_price + p.unrealized_pnl).sum();
+        self.equity = self.cash + value;
+        self.equity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_exits_close_the_position_exactly() {
+        let mut portfolio = Portfolio::new(1000.0);
+        portfolio.apply_fill("A", &Side::Buy, 21.0, 100.0);
+        // 21 - 30 * 0.7 leaves about 1e-14 behind in floating point
+        let realized: f64 =
+            (0..30).map(|_| portfolio.apply_fill("A", &Side::Sell, 0.7, 110.0)).sum();
+        assert!(portfolio.positions.is_empty());
+        assert_eq!(portfolio.qty("A"), 0.0);
+        assert!((realized - 210.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flip_reprices_the_remainder() {
+        let mut portfolio = Portfolio::new(1000.0);
+        portfolio.apply_fill("A", &Side::Buy, 10.0, 100.0);
+        assert_eq!(portfolio.apply_fill("A", &Side::Sell, 15.0, 110.0), 100.0);
+        let pos = portfolio.position("A").unwrap();
+        assert_eq!((pos.qty, pos.avg_price), (-5.0, 110.0));
+        assert_eq!(portfolio.apply_fill("A", &Side::Buy, 5.0, 105.0), 25.0);
+        assert!(portfolio.positions.is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::types::*;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A position opened and (partly) closed again. A position closed in several fills
+/// yields one round trip per closing fill.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoundTrip {
+    pub symbol: String,
+    /// Side of the entry: `Buy` for a long, `Sell` for a short.
+    pub side: Side,
+    pub qty: f64,
+    pub entry_ts: DateTime<Utc>,
+    pub entry_price: f64,
+    pub exit_ts: DateTime<Utc>,
+    pub exit_price: f64,
+    /// Realized PnL before fees.
+    pub pnl: f64,
+    /// Exit fee plus the matching share of entry fees.
+    pub fees: f64,
+    pub holding_ms: i64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EquityPoint {
+    pub ts: DateTime<Utc>,
+    pub equity: f64,
+}
+
+/// Wall-clock timing of a run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunTiming {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub elapsed_ms: u64,
+}
+
+/// Everything a consumer needs about a finished run, sent once before `Done`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunReport {
+    pub config: EngineConfig,
+    pub metrics: Metrics,
+    pub trades: Vec<RoundTrip>,
+    pub equity_curve: Vec<EquityPoint>,
+    /// Left out of runs with both a `seed` and a `start_ts`, whose event streams must be
+    /// byte-identical from one run to the next.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<RunTiming>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct OpenLot {
+    symbol: String,
+    /// Signed quantity still open.
+    qty: f64,
+    avg_price: f64,
+    entry_ts: DateTime<Utc>,
+    /// Entry fees not yet attributed to a round trip.
+    fees: f64,
+}
+
+/// Pairs entry and exit fills into round trips, per symbol.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TradeLedger {
+    open: Vec<OpenLot>,
+    pub closed: Vec<RoundTrip>,
+}
+
+impl TradeLedger {
+    pub fn record(&mut self, trade: &Trade) {
+        let signed = match trade.side {
+            Side::Buy => trade.qty,
+            Side::Sell => -trade.qty,
+        };
+        let idx = self.open.iter().position(|l| l.symbol == trade.symbol);
+
+        let Some(i) = idx else {
+            self.open.push(OpenLot {
+                symbol: trade.symbol.clone(),
+                qty: signed,
+                avg_price: trade.price,
+                entry_ts: trade.ts,
+                fees: trade.fee,
+            });
+            return;
+        };
+
+        let lot = &mut self.open[i];
+        if lot.qty.signum() == signed.signum() {
+            let held = lot.qty.abs();
+            lot.avg_price = (lot.avg_price * held + trade.price * trade.qty) / (held + trade.qty);
+            lot.qty += signed;
+            lot.fees += trade.fee;
+            return;
+        }
+
+        let closed = trade.qty.min(lot.qty.abs());
+        let share = closed / lot.qty.abs();
+        let entry_fees = lot.fees * share;
+        let dir = lot.qty.signum();
+        self.closed.push(RoundTrip {
+            symbol: lot.symbol.clone(),
+            side: if dir > 0.0 { Side::Buy } else { Side::Sell },
+            qty: closed,
+            entry_ts: lot.entry_ts,
+            entry_price: lot.avg_price,
+            exit_ts: trade.ts,
+            exit_price: trade.price,
+            pnl: closed * (trade.price - lot.avg_price) * dir,
+            fees: entry_fees + trade.fee * closed / trade.qty,
+            holding_ms: (trade.ts - lot.entry_ts).num_milliseconds(),
+        });
+        let rest = trade.qty - closed;
+        if rest > trade.qty * 1e-9 || (lot.qty + signed).abs() <= closed * 1e-9 {
+            self.open.remove(i);
+            if rest > trade.qty * 1e-9 {
+                // The fill crossed zero: the remainder opens a new lot at the fill price
+                self.open.push(OpenLot {
+                    symbol: trade.symbol.clone(),
+                    qty: signed.signum() * rest,
+                    avg_price: trade.price,
+                    entry_ts: trade.ts,
+                    fees: trade.fee * rest / trade.qty,
+                });
+            }
+        } else {
+            lot.fees -= entry_fees;
+            lot.qty += signed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(side: Side, qty: f64, price: f64, minute: i64) -> Trade {
+        Trade {
+            order_id: minute as u64,
+            side,
+            price,
+            qty,
+            symbol: "A".into(),
+            ts: DateTime::UNIX_EPOCH + chrono::Duration::minutes(minute),
+            fee: 0.0,
+            liquidity: Liquidity::Taker,
+        }
+    }
+
+    fn book(fills: &[Trade]) -> (TradeLedger, f64) {
+        let mut ledger = TradeLedger::default();
+        let mut portfolio = Portfolio::new(0.0);
+        let mut realized = 0.0;
+        for t in fills {
+            ledger.record(t);
+            realized += portfolio.apply_fill(&t.symbol, &t.side, t.qty, t.price);
+        }
+        (ledger, realized)
+    }
+
+    #[test]
+    fn close_books_one_round_trip() {
+        let (ledger, realized) =
+            book(&[fill(Side::Buy, 10.0, 100.0, 0), fill(Side::Sell, 10.0, 110.0, 1)]);
+        assert_eq!(ledger.closed.len(), 1);
+        let rt = &ledger.closed[0];
+        assert_eq!(
+            (rt.side, rt.qty, rt.entry_price, rt.exit_price),
+            (Side::Buy, 10.0, 100.0, 110.0)
+        );
+        assert_eq!(rt.pnl, 100.0);
+        assert_eq!(rt.pnl, realized);
+        assert!(ledger.open.is_empty());
+    }
+
+    #[test]
+    fn partial_close_keeps_the_rest_open() {
+        let mut entry = fill(Side::Buy, 10.0, 100.0, 0);
+        entry.fee = 2.0;
+        let (ledger, realized) = book(&[entry, fill(Side::Sell, 4.0, 105.0, 1)]);
+        assert_eq!(ledger.closed.len(), 1);
+        assert_eq!(ledger.closed[0].qty, 4.0);
+        assert_eq!(ledger.closed[0].pnl, realized);
+        assert!((ledger.closed[0].fees - 0.8).abs() < 1e-12);
+        let lot = &ledger.open[0];
+        assert_eq!((lot.qty, lot.avg_price), (6.0, 100.0));
+        assert!((lot.fees - 1.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn flip_opens_a_new_lot_from_the_remainder() {
+        let (ledger, realized) = book(&[
+            fill(Side::Buy, 10.0, 100.0, 0),
+            fill(Side::Sell, 15.0, 110.0, 1),
+            fill(Side::Buy, 5.0, 105.0, 2),
+        ]);
+        assert_eq!(ledger.closed.len(), 2);
+        let long = &ledger.closed[0];
+        assert_eq!((long.side, long.qty, long.pnl), (Side::Buy, 10.0, 100.0));
+        let short = &ledger.closed[1];
+        assert_eq!((short.side, short.qty), (Side::Sell, 5.0));
+        assert_eq!((short.entry_price, short.exit_price, short.pnl), (110.0, 105.0, 25.0));
+        assert_eq!(short.entry_ts, DateTime::UNIX_EPOCH + chrono::Duration::minutes(1));
+        assert_eq!(long.pnl + short.pnl, realized);
+        assert!(ledger.open.is_empty());
+    }
+}
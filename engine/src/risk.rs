@@ -0,0 +1,55 @@
+use crate::sizing::*;
+use crate::types::*;
+use chrono::{DateTime, Utc};
+
+/// Decides how large new positions may be and when open ones must be liquidated.
+pub trait RiskManager: Send {
+    /// Quantity for a new entry with every cap and rounding applied; 0 skips the entry.
+    fn entry_qty(&self, ctx: &SizingContext) -> f64;
+
+    /// Checked once a step's resting orders have traded. A call liquidates every position,
+    /// at the symbol's last mark when it has no bar in the step, and blocks new entries
+    /// until the next step.
+    fn margin_call(&self, portfolio: &Portfolio, ts: DateTime<Utc>) -> Option<MarginCall>;
+}
+
+/// Default risk layer: entries come from the configured sizer, capped at buying power
+/// and rounded down to the asset's lot; a maintenance breach triggers a margin call.
+pub struct MarginRisk {
+    pub margin: MarginConfig,
+    pub sizer: Box<dyn PositionSizer>,
+    pub assets: Vec<Asset>,
+}
+
+impl MarginRisk {
+    pub fn new(margin: MarginConfig, sizer: Box<dyn PositionSizer>) -> Self {
+        Self { margin, sizer, assets: Vec::new() }
+    }
+
+    /// Asset specs used for lot sizes; unknown symbols trade in any quantity.
+    pub fn with_assets(mut self, assets: Vec<Asset>) -> Self {
+        self.assets = assets;
+        self
+    }
+}
+
+impl RiskManager for MarginRisk {
+    fn entry_qty(&self, ctx: &SizingContext) -> f64 {
+        let max_qty = self.margin.buying_power(ctx.portfolio) / ctx.bar.close;
+        let lot_size = self
+            .assets
+            .iter()
+            .find(|a| a.symbol == ctx.bar.symbol)
+            .map(|a| a.lot_size)
+            .unwrap_or(0.0);
+        round_to_lot(self.sizer.size(ctx).min(max_qty), lot_size)
+    }
+
+    fn margin_call(&self, portfolio: &Portfolio, ts: DateTime<Utc>) -> Option<MarginCall> {
+        self.margin.is_breached(portfolio).then(|| MarginCall {
+            ts,
+            equity: portfolio.equity,
+            requirement: self.margin.maintenance_requirement(portfolio),
+        })
+    }
+}
@@ -0,0 +1,84 @@
+use crate::types::*;
+
+/// Inputs a sizer sees when a new position is opened.
+pub struct SizingContext<'a> {
+    pub portfolio: &'a Portfolio,
+    pub bar: &'a Bar,
+    /// Price distance to the protective stop, when one is attached.
+    pub stop_distance: Option<f64>,
+}
+
+/// Decides how many units a new position should have.
+pub trait PositionSizer: Send {
+    fn size(&self, ctx: &SizingContext) -> f64;
+}
+
+/// Commits a fixed fraction of current equity.
+pub struct FixedFractional {
+    pub fraction: f64,
+}
+
+impl PositionSizer for FixedFractional {
+    fn size(&self, ctx: &SizingContext) -> f64 {
+        ctx.portfolio.equity * self.fraction / ctx.bar.close
+    }
+}
+
+/// Commits the same notional amount every time.
+pub struct FixedNotional {
+    pub notional: f64,
+}
+
+impl PositionSizer for FixedNotional {
+    fn size(&self, ctx: &SizingContext) -> f64 {
+        self.notional / ctx.bar.close
+    }
+}
+
+/// Trades the same number of units every time.
+pub struct FixedUnits {
+    pub units: f64,
+}
+
+impl PositionSizer for FixedUnits {
+    fn size(&self, _ctx: &SizingContext) -> f64 {
+        self.units
+    }
+}
+
+/// Sizes so that being stopped out loses `risk_fraction` of equity. Without a stop the
+/// bar's range is taken as the expected adverse move.
+pub struct PercentRisk {
+    pub risk_fraction: f64,
+}
+
+impl PositionSizer for PercentRisk {
+    fn size(&self, ctx: &SizingContext) -> f64 {
+        let distance = ctx.stop_distance.unwrap_or(ctx.bar.high - ctx.bar.low);
+        if distance <= 0.0 {
+            return 0.0;
+        }
+        ctx.portfolio.equity * self.risk_fraction / distance
+    }
+}
+
+impl Sizing {
+    pub fn sizer(&self) -> Box<dyn PositionSizer> {
+        match *self {
+            Sizing::FixedFractional { fraction } => Box::new(FixedFractional { fraction }),
+            Sizing::FixedNotional { notional } => Box::new(FixedNotional { notional }),
+            Sizing::FixedUnits { units } => Box::new(FixedUnits { units }),
+            Sizing::PercentRisk { risk_fraction } => Box::new(PercentRisk { risk_fraction }),
+        }
+    }
+}
+
+/// Rounds `qty` down to a whole number of lots; a non-positive lot size leaves it as is.
+pub fn round_to_lot(qty: f64, lot_size: f64) -> f64 {
+    if lot_size > 0.0 {
+        // Tolerate float noise just below a lot boundary
+        (qty / lot_size + 1e-9).floor() * lot_size
+    } else {
+        qty
+    }
+}
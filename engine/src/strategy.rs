@@ -1,16 +1,39 @@
 use crate::types::*;
 use smartcore::linalg::basic::matrix::DenseMatrix;
-use smartcore::ensemble::random_forest_classifier::RandomForestClassifier;
+use smartcore::ensemble::random_forest_classifier::{
+    RandomForestClassifier, RandomForestClassifierParameters,
+};
+
+/// Position a strategy wants to hold in a symbol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Signal {
+    Long,
+    Flat,
+    Short,
+}
 
 pub trait Strategy: Send + Sync {
     fn train(&mut self, bars: &[Bar]) -> anyhow::Result<()>;
-    fn decide(&self, last: &Bar) -> bool; // true = long, false = flat
+    fn decide(&self, last: &Bar) -> Signal;
+
+    /// Learned state to store in a checkpoint; stateless strategies keep the default.
+    fn state(&self) -> anyhow::Result<serde_json::Value> {
+        Ok(serde_json::Value::Null)
+    }
+
+    /// Restores what `state` returned, in place of training.
+    fn restore(&mut self, _state: serde_json::Value) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 pub struct RfStrategy {
     pub trees: usize,
     pub max_depth: usize,
-    model: Option<RandomForestClassifier<f64, usize>>,
+    pub seed: u64,
+    /// Predicted down moves go short instead of flat.
+    pub allow_short: bool,
+    model: Option<RandomForestClassifier<f64, usize, DenseMatrix<f64>, Vec<usize>>>,
 }
 
 impl RfStrategy {
@@ -18,9 +41,29 @@ impl RfStrategy {
         Self {
             trees,
             max_depth,
+            seed: 0,
+            allow_short: false,
             model: None,
         }
     }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_shorts(mut self, allow_short: bool) -> Self {
+        self.allow_short = allow_short;
+        self
+    }
+
+    fn signal(&self, up: bool) -> Signal {
+        match (up, self.allow_short) {
+            (true, _) => Signal::Long,
+            (false, true) => Signal::Short,
+            (false, false) => Signal::Flat,
+        }
+    }
 }
 
 impl Strategy for RfStrategy {
@@ -33,24 +76,39 @@ impl Strategy for RfStrategy {
         let mut x = Vec::with_capacity(bars.len().saturating_sub(2) * 3);
         let mut y = Vec::with_capacity(bars.len().saturating_sub(2));
 
-        for w in bars.windows(3) {
-            let r1 = (w[1].close / w[0].close) - 1.0;
-            let r2 = (w[2].close / w[1].close) - 1.0;
-            let vol = (w[2].high - w[2].low) / w[2].close;
-            x.extend_from_slice(&[r1, vol, w[2].close - w[1].close]);
-            y.push(if r2 > 0.0 { 1 } else { 0 });
+        // Windows must not straddle two symbols when several are trained together
+        let mut symbols: Vec<&str> = Vec::new();
+        for b in bars {
+            if !symbols.contains(&b.symbol.as_str()) {
+                symbols.push(&b.symbol);
+            }
+        }
+
+        for symbol in symbols {
+            let series: Vec<&Bar> = bars.iter().filter(|b| b.symbol == symbol).collect();
+            for w in series.windows(3) {
+                let r1 = (w[1].close / w[0].close) - 1.0;
+                let r2 = (w[2].close / w[1].close) - 1.0;
+                let vol = (w[2].high - w[2].low) / w[2].close;
+                x.extend_from_slice(&[r1, vol, w[2].close - w[1].close]);
+                y.push(if r2 > 0.0 { 1 } else { 0 });
+            }
         }
 
-        let m = DenseMatrix::from_2d_array(&vec![x.chunks(3).map(|c| c.to_vec()).collect::<Vec<_>>()]);
-        let model = RandomForestClassifier::fit(&m, &y, Default::default())?;
+        let m = DenseMatrix::from_2d_vec(&x.chunks(3).map(|c| c.to_vec()).collect::<Vec<_>>());
+        let params = RandomForestClassifierParameters::default()
+            .with_n_trees(self.trees.min(u16::MAX as usize) as u16)
+            .with_max_depth(self.max_depth.min(u16::MAX as usize) as u16)
+            .with_seed(self.seed);
+        let model = RandomForestClassifier::fit(&m, &y, params)?;
         self.model = Some(model);
         Ok(())
     }
 
-    fn decide(&self, last: &Bar) -> bool {
+    fn decide(&self, last: &Bar) -> Signal {
         // Fallback heuristic if model not trained
         if self.model.is_none() {
-            return last.close > last.open;
+            return self.signal(last.close > last.open);
         }
 
         // Simple single-bar inference using last bar features
@@ -59,8 +117,17 @@ impl Strategy for RfStrategy {
             (last.high - last.low) / last.close,
             last.close - last.open,
         ]];
-        let m = DenseMatrix::from_2d_array(&feat);
+        let m = DenseMatrix::from_2d_vec(&feat);
         let pred = self.model.as_ref().unwrap().predict(&m).unwrap_or(vec![0]);
-        pred[0] == 1
+        self.signal(pred[0] == 1)
+    }
+
+    fn state(&self) -> anyhow::Result<serde_json::Value> {
+        Ok(serde_json::to_value(&self.model)?)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> anyhow::Result<()> {
+        self.model = serde_json::from_value(state)?;
+        Ok(())
     }
 }
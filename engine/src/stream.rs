@@ -0,0 +1,216 @@
+use crate::engine::{EngineControl, EngineEvent, EngineHandle};
+use crossbeam_channel::{Receiver, SendError, Sender};
+use futures_core::Stream;
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+/// Events the forwarding thread holds ahead of the slowest stream.
+const BUFFER: usize = 64;
+
+/// Async view of an engine's events for tokio consumers. It ends after `Done`.
+pub struct EventStream {
+    fanout: Arc<Fanout>,
+    id: u64,
+}
+
+impl Stream for EventStream {
+    type Item = EngineEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<EngineEvent>> {
+        let mut state = self.fanout.lock();
+        let FanoutState { queue, base, readers, finished, .. } = &mut *state;
+        let reader = readers.get_mut(&self.id).expect("stream is registered");
+        if let Some(evt) = queue.get((reader.pos - *base) as usize) {
+            let evt = evt.clone();
+            reader.pos += 1;
+            state.trim();
+            self.fanout.room.notify_all();
+            return Poll::Ready(Some(evt));
+        }
+        if *finished {
+            return Poll::Ready(None);
+        }
+        reader.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        let mut state = self.fanout.lock();
+        state.readers.remove(&self.id);
+        state.trim();
+        self.fanout.room.notify_all();
+    }
+}
+
+/// Shares one forwarding thread between all streams of a handle. Events stay queued
+/// until every open stream has read them, and while no stream is open they wait for the
+/// next one, so dropping a stream loses nothing.
+#[derive(Default)]
+pub(crate) struct Fanout {
+    state: Mutex<FanoutState>,
+    /// Signalled when the queue shrinks.
+    room: Condvar,
+}
+
+#[derive(Default)]
+struct FanoutState {
+    queue: VecDeque<EngineEvent>,
+    /// Position of the queue's front in the run's event sequence.
+    base: u64,
+    readers: HashMap<u64, Reader>,
+    next_id: u64,
+    started: bool,
+    /// The forwarding thread has passed on `Done` or the engine has gone.
+    finished: bool,
+}
+
+struct Reader {
+    /// Position of the next event this stream reads.
+    pos: u64,
+    waker: Option<Waker>,
+}
+
+impl Fanout {
+    fn lock(&self) -> MutexGuard<'_, FanoutState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn forward(self: Arc<Self>, rx: Receiver<EngineEvent>) {
+        while let Ok(evt) = rx.recv() {
+            let done = matches!(evt, EngineEvent::Done(_));
+            let mut state = self.lock();
+            while state.queue.len() >= BUFFER {
+                // Only this thread is left once the handle and its streams are gone
+                if Arc::strong_count(&self) == 1 {
+                    return;
+                }
+                let timeout = Duration::from_millis(100);
+                state = self.room.wait_timeout(state, timeout).unwrap_or_else(|e| e.into_inner()).0;
+            }
+            state.queue.push_back(evt);
+            state.wake();
+            if done {
+                break;
+            }
+        }
+        let mut state = self.lock();
+        state.finished = true;
+        state.wake();
+    }
+}
+
+impl FanoutState {
+    fn wake(&mut self) {
+        for waker in self.readers.values_mut().filter_map(|r| r.waker.take()) {
+            waker.wake();
+        }
+    }
+
+    /// Drops the events every open stream has read.
+    fn trim(&mut self) {
+        let Some(min) = self.readers.values().map(|r| r.pos).min() else {
+            return;
+        };
+        while self.base < min && self.queue.pop_front().is_some() {
+            self.base += 1;
+        }
+    }
+}
+
+/// Control sender usable from async code.
+#[derive(Clone)]
+pub struct ControlSender {
+    tx: Sender<EngineControl>,
+}
+
+impl ControlSender {
+    /// Fails only once the engine has exited.
+    pub async fn send(&self, ctrl: EngineControl) -> Result<(), SendError<EngineControl>> {
+        // The control channel is unbounded, so this never waits
+        self.tx.send(ctrl)
+    }
+}
+
+impl EngineHandle {
+    /// Streams the run's events asynchronously. All streams of a handle share one
+    /// forwarding thread that moves events from `rx_evt`, so they compete with direct
+    /// readers of `rx_evt` and keep the engine's backpressure policy in effect. Each open
+    /// stream sees every event; a new stream starts at the oldest event an open stream
+    /// has not read yet, or at the first event no stream has taken.
+    pub fn events(&self) -> EventStream {
+        let mut state = self.fanout.lock();
+        if !state.started {
+            state.started = true;
+            let (fanout, rx) = (self.fanout.clone(), self.rx_evt.clone());
+            std::thread::spawn(move || fanout.forward(rx));
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        let pos = state.base;
+        state.readers.insert(id, Reader { pos, waker: None });
+        EventStream { fanout: self.fanout.clone(), id }
+    }
+
+    pub fn control(&self) -> ControlSender {
+        ControlSender { tx: self.tx_ctrl.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::RunStatus;
+    use crate::error::EngineError;
+    use crossbeam_channel::unbounded;
+
+    fn handle(n: usize) -> EngineHandle {
+        let (tx_evt, rx_evt) = unbounded();
+        for i in 0..n {
+            tx_evt.send(EngineEvent::Error(EngineError::Export(i.to_string()))).unwrap();
+        }
+        tx_evt.send(EngineEvent::Done(RunStatus::Completed)).unwrap();
+        let tx_ctrl = unbounded().0;
+        EngineHandle { tx_ctrl, rx_evt, drops: Default::default(), fanout: Default::default() }
+    }
+
+    async fn next(events: &mut EventStream) -> Option<String> {
+        std::future::poll_fn(|cx| Pin::new(&mut *events).poll_next(cx)).await.map(|evt| match evt {
+            EngineEvent::Error(EngineError::Export(i)) => i,
+            other => format!("{other:?}"),
+        })
+    }
+
+    async fn rest(events: &mut EventStream) -> Vec<String> {
+        let mut seen = Vec::new();
+        while let Some(evt) = next(events).await {
+            seen.push(evt);
+        }
+        seen
+    }
+
+    #[tokio::test]
+    async fn dropped_stream_leaves_its_events_to_the_next() {
+        let handle = handle(100);
+        let mut first = handle.events();
+        assert_eq!(next(&mut first).await.as_deref(), Some("0"));
+        assert_eq!(next(&mut first).await.as_deref(), Some("1"));
+        drop(first);
+        let seen = rest(&mut handle.events()).await;
+        let expected = (2..100).map(|i| i.to_string()).chain(["Done(Completed)".to_string()]);
+        assert_eq!(seen, expected.collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn open_streams_all_see_every_event() {
+        let handle = handle(100);
+        let (mut a, mut b) = (handle.events(), handle.events());
+        let (a, b) = tokio::join!(rest(&mut a), rest(&mut b));
+        assert_eq!(a.len(), 101);
+        assert_eq!(a, b);
+    }
+}
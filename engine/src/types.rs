@@ -1,6 +1,8 @@
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 
+use crate::channel::Backpressure;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Asset {
     pub symbol: String,
@@ -17,17 +19,53 @@ pub struct Bar {
     pub close: f64,
     pub volume: f64,
     pub symbol: String,
+    /// Hidden regime a synthetic bar was drawn in, when its price model has one. Ground
+    /// truth for judging strategies, not an input for them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regime: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Side {
     Buy,
     Sell,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl Side {
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum OrderType {
     Market,
+    Limit { price: f64 },
+    Stop { price: f64 },
+    /// Becomes a limit order at `limit` once `stop` trades.
+    StopLimit { stop: f64, limit: f64 },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum TimeInForce {
+    /// Good till cancelled.
+    #[default]
+    Gtc,
+    /// Expires at the end of the (UTC) day it was placed.
+    Day,
+    /// Immediate or cancel: whatever can't fill on submission is cancelled.
+    Ioc,
+}
+
+/// Exit legs attached to an entry order. They are placed once the entry fills and
+/// cancel each other (OCO) when one of them fills.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bracket {
+    pub take_profit: f64,
+    pub stop_loss: f64,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -37,15 +75,89 @@ pub struct Order {
     pub side: Side,
     pub qty: f64,
     pub ty: OrderType,
+    #[serde(default)]
+    pub tif: TimeInForce,
+    #[serde(default)]
+    pub bracket: Option<Bracket>,
+    /// Entry order this one was attached to, for bracket legs.
+    #[serde(default)]
+    pub parent_id: Option<u64>,
+    /// Orders sharing a group id are one-cancels-other.
+    #[serde(default)]
+    pub oco_group: Option<u64>,
+}
+
+impl Order {
+    pub fn new(symbol: &str, side: Side, qty: f64, ty: OrderType) -> Self {
+        Self {
+            id: 0,
+            symbol: symbol.to_string(),
+            side,
+            qty,
+            ty,
+            tif: TimeInForce::Gtc,
+            bracket: None,
+            parent_id: None,
+            oco_group: None,
+        }
+    }
+
+    pub fn market(symbol: &str, side: Side, qty: f64) -> Self {
+        Self::new(symbol, side, qty, OrderType::Market)
+    }
+
+    pub fn with_tif(mut self, tif: TimeInForce) -> Self {
+        self.tif = tif;
+        self
+    }
+
+    pub fn with_bracket(mut self, bracket: Bracket) -> Self {
+        self.bracket = Some(bracket);
+        self
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Trade {
     pub order_id: u64,
+    pub side: Side,
     pub price: f64,
     pub qty: f64,
     pub symbol: String,
     pub ts: DateTime<Utc>,
+    /// Commission charged for this fill.
+    #[serde(default)]
+    pub fee: f64,
+    #[serde(default)]
+    pub liquidity: Liquidity,
+}
+
+/// Whether a fill added liquidity (resting limit) or removed it (market, stop).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Liquidity {
+    Maker,
+    #[default]
+    Taker,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CancelReason {
+    /// Cancelled by the engine or strategy, e.g. bracket legs of a position exited by signal.
+    Requested,
+    /// A sibling in the same OCO group filled.
+    Oco,
+    /// A DAY order outlived its session.
+    Expired,
+    /// An IOC order could not fill on submission.
+    Unfilled,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cancel {
+    pub order_id: u64,
+    pub symbol: String,
+    pub reason: CancelReason,
+    pub ts: DateTime<Utc>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -65,17 +177,61 @@ pub struct Portfolio {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Metrics {
+    /// Realized PnL before fees.
     pub pnl: f64,
+    /// Commissions paid so far.
+    #[serde(default)]
+    pub fees: f64,
+    /// Largest peak-to-trough equity decline so far, as a fraction of the peak.
     pub max_drawdown: f64,
+    /// Annualized Sharpe ratio of per-bar returns.
     pub sharpe: f64,
     pub win_rate: f64,
+    /// Closing fills counted as trades.
     pub trades: usize,
+    /// Current decline from the equity peak.
+    #[serde(default)]
+    pub drawdown: f64,
+    /// Longest time spent below a previous equity peak, in bars.
+    #[serde(default)]
+    pub max_drawdown_bars: usize,
+    /// Annualized Sortino ratio (downside deviation only).
+    #[serde(default)]
+    pub sortino: f64,
+    /// CAGR over max drawdown.
+    #[serde(default)]
+    pub calmar: f64,
+    /// Compound annual growth rate of equity.
+    #[serde(default)]
+    pub cagr: f64,
+    /// Annualized standard deviation of per-bar returns.
+    #[serde(default)]
+    pub volatility: f64,
+    /// Gross profit over gross loss of closed trades.
+    #[serde(default)]
+    pub profit_factor: f64,
+    /// Average realized PnL per trade.
+    #[serde(default)]
+    pub expectancy: f64,
+    #[serde(default)]
+    pub avg_win: f64,
+    /// Average size of a losing trade, as a positive amount.
+    #[serde(default)]
+    pub avg_loss: f64,
+    /// Fraction of bars with at least one open position.
+    #[serde(default)]
+    pub time_in_market: f64,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Mode {
+    /// Steps paced by `bar_interval_ms` on the wall clock, scaled by the playback speed.
     Simulate,
+    /// Steps as fast as they are processed.
     Backtest,
+    /// Trades bars as a feed delivers them, after training on the data source's history.
+    /// Without a feed from `EngineBuilder::with_feed`, synthetic test bars starting now
+    /// are released as they close on the wall clock.
     Forwardtest,
 }
 
@@ -89,4 +245,342 @@ pub struct EngineConfig {
     pub train_split: f32,
     pub mode: Mode,
     pub starting_cash: f64,
+    /// Seeds data generation and model training; together with `start_ts` it makes runs reproducible.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Timestamp of the first generated bar; defaults to the wall clock at spawn.
+    #[serde(default)]
+    pub start_ts: Option<DateTime<Utc>>,
+    /// When set, entries are bracket orders with take-profit/stop-loss legs this far from the fill.
+    #[serde(default)]
+    pub bracket: Option<BracketConfig>,
+    /// Order type and time in force of entries; market orders good till cancelled by default.
+    #[serde(default)]
+    pub entry: EntryConfig,
+    #[serde(default)]
+    pub commission: CommissionModel,
+    #[serde(default)]
+    pub slippage: SlippageModel,
+    /// Largest share of a bar's volume one order may take; the rest fills on later bars.
+    #[serde(default)]
+    pub max_volume_participation: Option<f64>,
+    /// Per-symbol lot and tick sizes; symbols without an entry use a 0.01 tick.
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+    /// Lets the strategy hold short positions.
+    #[serde(default)]
+    pub allow_short: bool,
+    #[serde(default)]
+    pub margin: MarginConfig,
+    #[serde(default)]
+    pub sizing: Sizing,
+    /// Capacity of the event channel; unbounded when not set.
+    #[serde(default)]
+    pub event_buffer: Option<usize>,
+    /// What to do when a bounded event channel is full.
+    #[serde(default)]
+    pub backpressure: Backpressure,
+    /// Emit an `EngineEvent::Checkpoint` after every this many steps.
+    #[serde(default)]
+    pub checkpoint_every: Option<u64>,
+    /// Path of a JSON-lines journal that records every event of the run.
+    #[serde(default)]
+    pub journal: Option<String>,
+    /// Process the synthetic bars are drawn from.
+    #[serde(default)]
+    pub price_model: PriceModel,
+    /// Correlates the synthetic symbols' returns; independent when not set.
+    #[serde(default)]
+    pub dependence: Option<Dependence>,
+    /// Where the run's bars come from; synthetic unless set.
+    #[serde(default)]
+    pub data_source: BarSource,
+    /// Writes the run's bars, trades, equity curve and metrics to files once it finishes.
+    #[serde(default)]
+    pub export: Option<ExportConfig>,
+}
+
+/// Source of a run's bars. `length_bars`, `price_model` and `dependence` only shape
+/// synthetic data; `bar_interval_ms` should match the spacing of loaded history.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum BarSource {
+    #[default]
+    Synthetic,
+    /// OHLCV history from CSV files, for the symbols listed in `symbols`.
+    Csv(Box<CsvConfig>),
+    /// Bars from Parquet or Arrow IPC files, told apart by extension. Needs the
+    /// `columnar` feature.
+    Columnar { paths: Vec<String> },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CsvConfig {
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub columns: CsvColumns,
+    /// chrono format of the timestamp column, `unix` or `unix_ms`; RFC 3339 when not set.
+    #[serde(default)]
+    pub timestamp_format: Option<String>,
+    /// IANA zone of timestamps that carry no offset, e.g. `America/New_York`; UTC when not set.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Takes each file's symbol from its name (`AAPL.csv` holds `AAPL`) instead of a column.
+    #[serde(default)]
+    pub symbol_from_filename: bool,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    /// Drops malformed rows with a warning instead of failing the run.
+    #[serde(default)]
+    pub skip_malformed: bool,
+}
+
+/// File format for columnar imports and exports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColumnarFormat {
+    #[default]
+    Parquet,
+    /// The Arrow IPC file format, also known as Feather v2.
+    ArrowIpc,
+}
+
+impl ColumnarFormat {
+    /// Format implied by a file's extension.
+    pub fn from_path(path: impl AsRef<std::path::Path>) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "parquet" | "pq" => Some(ColumnarFormat::Parquet),
+            "arrow" | "ipc" | "feather" => Some(ColumnarFormat::ArrowIpc),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ColumnarFormat::Parquet => "parquet",
+            ColumnarFormat::ArrowIpc => "arrow",
+        }
+    }
+}
+
+/// Directory a finished run writes `bars`, `trades`, `equity_curve` and `metrics` files to,
+/// one row per record. Needs the `columnar` feature.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportConfig {
+    pub dir: String,
+    #[serde(default)]
+    pub format: ColumnarFormat,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+/// Header names of the CSV columns. Without a symbol column, files must hold a single
+/// symbol, named by the file or by the only entry in `symbols`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvColumns {
+    pub timestamp: String,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub volume: String,
+    pub symbol: Option<String>,
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        Self {
+            timestamp: "timestamp".into(),
+            open: "open".into(),
+            high: "high".into(),
+            low: "low".into(),
+            close: "close".into(),
+            volume: "volume".into(),
+            symbol: Some("symbol".into()),
+        }
+    }
+}
+
+/// Price process for synthetic bars. Drift and volatility of the continuous models are
+/// annualized; GARCH parameters are per bar.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum PriceModel {
+    /// Returns drawn uniformly from `-vol..vol` per bar, with random wicks.
+    Uniform { vol: f64 },
+    /// Geometric Brownian motion.
+    Gbm { drift: f64, volatility: f64 },
+    /// Merton jump-diffusion: GBM plus Poisson jumps (`jump_intensity` per year) with
+    /// normally distributed log sizes.
+    JumpDiffusion {
+        drift: f64,
+        volatility: f64,
+        jump_intensity: f64,
+        jump_mean: f64,
+        jump_std: f64,
+    },
+    /// GARCH(1,1) returns: variance `omega + alpha * r^2 + beta * variance` of the last bar.
+    Garch { omega: f64, alpha: f64, beta: f64 },
+    /// GBM whose drift and volatility follow a hidden Markov regime. `transitions[i][j]`
+    /// is the chance of moving from regime `i` to `j` before each bar; every symbol starts
+    /// in the first regime and switches on its own.
+    RegimeSwitching { regimes: Vec<Regime>, transitions: Vec<Vec<f64>> },
+}
+
+/// One state of a regime-switching price model, e.g. bull, bear or sideways.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Regime {
+    pub name: String,
+    pub drift: f64,
+    pub volatility: f64,
+}
+
+impl Default for PriceModel {
+    fn default() -> Self {
+        PriceModel::Uniform { vol: 0.01 }
+    }
+}
+
+/// Dependence between synthetic symbols, as a matrix in `symbols` order. It applies to the
+/// diffusion of the normally driven price models; jumps stay independent.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Dependence {
+    /// Correlation of returns; every symbol keeps the price model's volatility.
+    Correlation { matrix: Vec<Vec<f64>> },
+    /// Covariance of annualized log returns; its diagonal sets each symbol's volatility.
+    Covariance { matrix: Vec<Vec<f64>> },
+}
+
+/// Position sizing rule for new entries; quantities are rounded down to the asset's lot size.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Sizing {
+    /// Fraction of equity.
+    FixedFractional { fraction: f64 },
+    /// Fixed amount of cash per position.
+    FixedNotional { notional: f64 },
+    /// Fixed number of units per position.
+    FixedUnits { units: f64 },
+    /// Fraction of equity lost if the stop (or one bar range) is hit.
+    PercentRisk { risk_fraction: f64 },
+}
+
+impl Default for Sizing {
+    fn default() -> Self {
+        Sizing::FixedFractional { fraction: 0.10 }
+    }
+}
+
+/// Margin requirements as fractions of gross exposure. `initial` bounds leverage on new
+/// positions (0.5 = 2x); falling below `maintenance` triggers a margin call and liquidation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MarginConfig {
+    pub initial: f64,
+    pub maintenance: f64,
+}
+
+impl Default for MarginConfig {
+    fn default() -> Self {
+        Self {
+            initial: 1.0,
+            maintenance: 0.25,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MarginCall {
+    pub ts: DateTime<Utc>,
+    pub equity: f64,
+    /// Maintenance margin the account failed to cover.
+    pub requirement: f64,
+}
+
+/// How far taker fills move against the order from the reference price.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum SlippageModel {
+    #[default]
+    None,
+    /// A fixed number of the asset's ticks.
+    FixedTicks { ticks: f64 },
+    /// A fraction of the bar's high-low range.
+    RangeFraction { fraction: f64 },
+    /// Square-root market impact: `coefficient * range * sqrt(qty / volume)`.
+    SqrtImpact { coefficient: f64 },
+}
+
+/// How simulated fills are charged.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum CommissionModel {
+    #[default]
+    None,
+    /// Flat amount per order, charged on its first fill.
+    Fixed { per_trade: f64 },
+    /// Amount per unit traded, with an optional minimum per order.
+    PerShare {
+        rate: f64,
+        #[serde(default)]
+        min: f64,
+    },
+    /// Basis points of fill notional.
+    Bps { bps: f64 },
+    /// Maker/taker basis points picked by cumulative traded notional; the highest tier whose
+    /// `min_volume` has been reached applies.
+    Tiered { tiers: Vec<FeeTier> },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub min_volume: f64,
+    pub maker_bps: f64,
+    pub taker_bps: f64,
+}
+
+/// Distances of bracket legs from the entry price, as fractions (0.02 = 2%).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BracketConfig {
+    pub take_profit_pct: f64,
+    pub stop_loss_pct: f64,
+}
+
+/// How entries are placed. An entry still working holds off new signals for its symbol
+/// until it fills, expires (`Day`) or is cancelled (`Ioc`).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EntryConfig {
+    #[serde(default)]
+    pub order: EntryOrder,
+    #[serde(default)]
+    pub tif: TimeInForce,
+}
+
+/// Entry order type, priced from the close of the bar that signalled it. Offsets are
+/// fractions of that close (0.01 = 1%).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum EntryOrder {
+    #[default]
+    Market,
+    /// Waits for a better price: buys `offset_pct` below the close, sells above it.
+    Limit { offset_pct: f64 },
+    /// Waits for a breakout: buys once price rises `offset_pct` above the close, sells
+    /// once it falls as far below.
+    Stop { offset_pct: f64 },
+    /// Triggers like `Stop` at `stop_pct`, then pays at most `limit_pct` beyond the close.
+    StopLimit { stop_pct: f64, limit_pct: f64 },
+}
+
+impl EntryConfig {
+    /// The entry for `qty` of `bar.symbol` on `side`.
+    pub fn order(&self, bar: &Bar, side: Side, qty: f64) -> Order {
+        // Away from the close in the trade's favour (limits) or against it (stops)
+        let dir = if side == Side::Buy { 1.0 } else { -1.0 };
+        let price = |pct: f64| bar.close * (1.0 + dir * pct);
+        let ty = match self.order {
+            EntryOrder::Market => OrderType::Market,
+            EntryOrder::Limit { offset_pct } => OrderType::Limit { price: price(-offset_pct) },
+            EntryOrder::Stop { offset_pct } => OrderType::Stop { price: price(offset_pct) },
+            EntryOrder::StopLimit { stop_pct, limit_pct } => {
+                OrderType::StopLimit { stop: price(stop_pct), limit: price(limit_pct) }
+            }
+        };
+        Order::new(&bar.symbol, side, qty, ty).with_tif(self.tif)
+    }
 }
@@ -1,4 +1,5 @@
 mod db;
+mod paths;
 mod routes;
 mod state;
 
@@ -39,6 +40,8 @@ async fn main() {
 
     let state = std::sync::Arc::new(state::AppState {
         engine: std::sync::Mutex::new(None),
+        feed: std::sync::Mutex::new(None),
+        data_dir: paths::DataDir::from_env().expect("data directory"),
         pool_app,
         pool_ts,
     });
@@ -52,6 +55,10 @@ async fn main() {
         .route("/health", get(|| async { "ok" }))
         .route("/api/assets", get(|| async { axum::Json(Vec::<String>::new()) }))
         .route("/api/sim/start", post(routes::start_sim))
+        .route("/api/sim/resume", post(routes::resume_sim))
+        .route("/api/sim/replay", post(routes::replay_sim))
+        .route("/api/sim/control", post(routes::sim_control))
+        .route("/api/sim/bars", post(routes::push_bars))
         .route("/api/sim/status", get(routes::sim_status))
         .route("/ws/stream", get(routes::ws_stream))
         .with_state(state)
@@ -59,7 +66,7 @@ async fn main() {
 
     let host = std::env::var("SERVER_HOST").unwrap_or_else(|_| "127.0.0.1".into());
     let port = std::env::var("SERVER_PORT").unwrap_or_else(|_| "8080".into());
-    let addr = format!("{}:{}", host, port).parse().unwrap();
+    let addr: std::net::SocketAddr = format!("{}:{}", host, port).parse().unwrap();
 
     tracing::info!("server listening on http://{}", addr);
 
@@ -0,0 +1,88 @@
+use nexow_engine::{BarSource, ConfigError, EngineConfig, FieldError};
+use std::path::{Component, Path, PathBuf};
+
+/// The only directory request bodies may name files in, from `SERVER_DATA_DIR`
+/// (`./data` by default). Relative paths are taken from it.
+pub struct DataDir {
+    root: PathBuf,
+}
+
+impl DataDir {
+    pub fn from_env() -> std::io::Result<Self> {
+        let root = std::env::var("SERVER_DATA_DIR").unwrap_or_else(|_| "data".into());
+        std::fs::create_dir_all(&root)?;
+        Ok(Self { root: Path::new(&root).canonicalize()? })
+    }
+
+    /// Resolves `path` and rejects it unless it stays inside the data directory.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let resolved = self.root.join(path);
+        if resolved.components().any(|c| c == Component::ParentDir) {
+            return Err("must not contain `..`".into());
+        }
+        // Symlinks can lead out of the directory, so check where the existing part points
+        let existing = resolved.ancestors().find(|p| p.exists()).unwrap_or(&self.root);
+        let real = existing.canonicalize().map_err(|e| e.to_string())?;
+        if !real.starts_with(&self.root) {
+            return Err(format!("must be inside the data directory {}", self.root.display()));
+        }
+        Ok(resolved)
+    }
+
+    /// Rewrites every file path in `config` to its resolved form, reporting each one
+    /// outside the data directory under its field path.
+    pub fn confine(&self, config: &mut EngineConfig) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        let mut confine = |field: String, path: &mut String| match self.resolve(path) {
+            Ok(resolved) => *path = resolved.to_string_lossy().into_owned(),
+            Err(message) => errors.push(FieldError { field, message }),
+        };
+
+        if let Some(journal) = &mut config.journal {
+            confine("journal".into(), journal);
+        }
+        let paths = match &mut config.data_source {
+            BarSource::Synthetic => None,
+            BarSource::Csv(csv) => Some(&mut csv.paths),
+            BarSource::Columnar { paths } => Some(paths),
+        };
+        for (i, path) in paths.into_iter().flatten().enumerate() {
+            confine(format!("data_source.paths[{i}]"), path);
+        }
+        if let Some(export) = &mut config.export {
+            confine("export.dir".into(), &mut export.dir);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { errors })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_must_stay_inside_the_data_directory() {
+        let root = std::env::temp_dir().join(format!("nexow-data-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("runs")).unwrap();
+        let data = DataDir { root: root.canonicalize().unwrap() };
+
+        assert_eq!(data.resolve("runs/a.jsonl").unwrap(), data.root.join("runs/a.jsonl"));
+        let inside = data.root.join("new/export");
+        assert_eq!(data.resolve(inside.to_str().unwrap()).unwrap(), inside);
+        assert!(data.resolve("../a.jsonl").is_err());
+        assert!(data.resolve("runs/../../a.jsonl").is_err());
+        assert!(data.resolve("/etc/passwd").is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", root.join("etc")).unwrap();
+            assert!(data.resolve("etc/passwd").is_err());
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
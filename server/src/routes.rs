@@ -1,13 +1,20 @@
 use axum::{
     extract::{ws::{Message, WebSocket}, State, WebSocketUpgrade},
+    http::StatusCode,
     response::IntoResponse,
     Json,
 };
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use crate::state::AppState;
-use nexow_engine::{Engine, EngineConfig, EngineEvent, Mode};
+use nexow_engine::{
+    feed_channel, Asset, Backpressure, Bar, BarSource, BracketConfig, Checkpoint,
+    CommissionModel, ConfigError, Dependence, Engine, EngineConfig, EngineControl, EntryConfig,
+    ExportConfig, FieldError, JournalReader, MarginConfig, Mode, PriceModel, Sizing, SlippageModel,
+};
 
 #[derive(Deserialize)]
 pub struct StartSimReq {
@@ -19,6 +26,44 @@ pub struct StartSimReq {
     pub train_split: f32,
     pub mode: String,
     pub starting_cash: f64,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub start_ts: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub bracket: Option<BracketConfig>,
+    #[serde(default)]
+    pub entry: EntryConfig,
+    #[serde(default)]
+    pub commission: CommissionModel,
+    #[serde(default)]
+    pub slippage: SlippageModel,
+    #[serde(default)]
+    pub max_volume_participation: Option<f64>,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+    #[serde(default)]
+    pub allow_short: bool,
+    #[serde(default)]
+    pub margin: MarginConfig,
+    #[serde(default)]
+    pub sizing: Sizing,
+    #[serde(default)]
+    pub event_buffer: Option<usize>,
+    #[serde(default)]
+    pub backpressure: Backpressure,
+    #[serde(default)]
+    pub checkpoint_every: Option<u64>,
+    #[serde(default)]
+    pub journal: Option<String>,
+    #[serde(default)]
+    pub price_model: PriceModel,
+    #[serde(default)]
+    pub dependence: Option<Dependence>,
+    #[serde(default)]
+    pub data_source: BarSource,
+    #[serde(default)]
+    pub export: Option<ExportConfig>,
 }
 
 #[derive(Serialize)]
@@ -29,14 +74,19 @@ pub struct StartSimRes {
 pub async fn start_sim(
     State(state): State<Arc<AppState>>,
     Json(req): Json<StartSimReq>,
-) -> Json<StartSimRes> {
+) -> Result<Json<StartSimRes>, (StatusCode, Json<ConfigError>)> {
     let mode = match req.mode.as_str() {
+        "simulate" => Mode::Simulate,
         "backtest" => Mode::Backtest,
         "forwardtest" => Mode::Forwardtest,
-        _ => Mode::Simulate,
+        other => {
+            let message = format!("unknown mode `{other}`; use simulate, backtest or forwardtest");
+            let errors = vec![FieldError { field: "mode".into(), message }];
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(ConfigError { errors })));
+        }
     };
 
-    let cfg = EngineConfig {
+    let mut cfg = EngineConfig {
         symbols: req.symbols,
         bar_interval_ms: req.bar_interval_ms,
         length_bars: req.length_bars,
@@ -45,23 +95,215 @@ pub async fn start_sim(
         train_split: req.train_split,
         mode,
         starting_cash: req.starting_cash,
+        seed: req.seed,
+        start_ts: req.start_ts,
+        bracket: req.bracket,
+        entry: req.entry,
+        commission: req.commission,
+        slippage: req.slippage,
+        max_volume_participation: req.max_volume_participation,
+        assets: req.assets,
+        allow_short: req.allow_short,
+        margin: req.margin,
+        sizing: req.sizing,
+        event_buffer: req.event_buffer,
+        backpressure: req.backpressure,
+        checkpoint_every: req.checkpoint_every,
+        journal: req.journal,
+        price_model: req.price_model,
+        dependence: req.dependence,
+        data_source: req.data_source,
+        export: req.export,
+    };
+
+    // Bad configs are rejected here rather than failing inside the engine thread
+    cfg.validate().map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, Json(e)))?;
+    state.data_dir.confine(&mut cfg).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, Json(e)))?;
+
+    launch(&state, cfg, None);
+
+    Ok(Json(StartSimRes {
+        status: "started".into(),
+    }))
+}
+
+/// Continues a run from a checkpoint taken from the event stream.
+pub async fn resume_sim(
+    State(state): State<Arc<AppState>>,
+    Json(mut checkpoint): Json<Checkpoint>,
+) -> Result<Json<StartSimRes>, (StatusCode, Json<ConfigError>)> {
+    let invalid = |e| (StatusCode::UNPROCESSABLE_ENTITY, Json(e));
+    checkpoint.config.validate().map_err(invalid)?;
+    state.data_dir.confine(&mut checkpoint.config).map_err(invalid)?;
+
+    launch(&state, checkpoint.config.clone(), Some(checkpoint));
+
+    Ok(Json(StartSimRes {
+        status: "resumed".into(),
+    }))
+}
+
+/// Runs `config` as the running engine, from `checkpoint` when given. A forward test on
+/// loaded history trades the bars pushed to `POST /api/sim/bars`.
+fn launch(state: &AppState, config: EngineConfig, checkpoint: Option<Checkpoint>) {
+    let live = matches!(config.mode, Mode::Forwardtest)
+        && !matches!(config.data_source, BarSource::Synthetic);
+    let mut builder = Engine::builder(config);
+    let mut feed = None;
+    if live {
+        let (tx, rx) = feed_channel();
+        builder = builder.with_feed(rx);
+        feed = Some(tx);
+    }
+    let handle = match checkpoint {
+        Some(checkpoint) => builder.resume(checkpoint),
+        None => builder.spawn(),
+    };
+
+    *state.engine.lock().unwrap() = Some(handle);
+    *state.feed.lock().unwrap() = feed;
+}
+
+#[derive(Deserialize)]
+pub struct ReplaySimReq {
+    pub path: String,
+    /// Multiple of the recorded pace; omitted replays as fast as the stream is read.
+    #[serde(default)]
+    pub speed: Option<f64>,
+}
+
+/// Re-emits a recorded journal as the running engine.
+pub async fn replay_sim(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ReplaySimReq>,
+) -> (StatusCode, Json<StartSimRes>) {
+    let path = match state.data_dir.resolve(&req.path) {
+        Ok(path) => path,
+        Err(e) => {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(StartSimRes {
+                    status: format!("path {e}"),
+                }),
+            )
+        }
+    };
+    let journal = match JournalReader::open(path) {
+        Ok(journal) => journal,
+        Err(e) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(StartSimRes {
+                    status: format!("cannot open journal: {e}"),
+                }),
+            )
+        }
     };
 
-    let handle = Engine::spawn(cfg);
+    let handle = Engine::replay(journal, req.speed);
 
     {
         let mut eng = state.engine.lock().unwrap();
         *eng = Some(handle);
     }
+    *state.feed.lock().unwrap() = None;
 
-    Json(StartSimRes {
-        status: "started".into(),
-    })
+    (
+        StatusCode::OK,
+        Json(StartSimRes {
+            status: "replaying".into(),
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct SimControlReq {
+    pub action: String,
+    #[serde(default)]
+    pub steps: Option<u64>,
+    #[serde(default)]
+    pub speed: Option<f64>,
+}
+
+pub async fn sim_control(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<SimControlReq>,
+) -> (StatusCode, Json<StartSimRes>) {
+    let ctrl = match req.action.as_str() {
+        "stop" => EngineControl::Stop,
+        "pause" => EngineControl::Pause,
+        "resume" => EngineControl::Resume,
+        "step" => EngineControl::Step(req.steps.unwrap_or(1)),
+        "checkpoint" => EngineControl::Checkpoint,
+        "speed" => match req.speed {
+            Some(speed) => EngineControl::SetSpeed(speed),
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(StartSimRes {
+                        status: "missing speed".into(),
+                    }),
+                )
+            }
+        },
+        other => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(StartSimRes {
+                    status: format!("unknown action: {other}"),
+                }),
+            )
+        }
+    };
+
+    let control = {
+        let eng = state.engine.lock().unwrap();
+        eng.as_ref().map(|h| h.control())
+    };
+    let sent = match control {
+        Some(control) => control.send(ctrl).await.is_ok(),
+        None => false,
+    };
+
+    if sent {
+        (StatusCode::OK, Json(StartSimRes { status: "ok".into() }))
+    } else {
+        (
+            StatusCode::CONFLICT,
+            Json(StartSimRes {
+                status: "not running".into(),
+            }),
+        )
+    }
+}
+
+/// Hands the next time step, one bar per symbol, to a forward test waiting on its feed.
+pub async fn push_bars(
+    State(state): State<Arc<AppState>>,
+    Json(step): Json<Vec<Bar>>,
+) -> (StatusCode, Json<StartSimRes>) {
+    let sent = {
+        let feed = state.feed.lock().unwrap();
+        feed.as_ref().is_some_and(|tx| tx.send(step).is_ok())
+    };
+
+    if sent {
+        (StatusCode::OK, Json(StartSimRes { status: "ok".into() }))
+    } else {
+        (
+            StatusCode::CONFLICT,
+            Json(StartSimRes {
+                status: "no forward test is waiting on bars".into(),
+            }),
+        )
+    }
 }
 
 pub async fn sim_status(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let eng = state.engine.lock().unwrap();
     Json(serde_json::json!({
-        "running": state.engine.lock().unwrap().is_some()
+        "running": eng.is_some(),
+        "dropped_events": eng.as_ref().map(|h| h.drops.total()).unwrap_or(0),
     }))
 }
 
@@ -73,27 +315,33 @@ pub async fn ws_stream(
 }
 
 async fn handle_ws(mut socket: WebSocket, state: Arc<AppState>) {
-    let rx = {
+    let events = {
         let eng = state.engine.lock().unwrap();
-        if let Some(h) = eng.as_ref() {
-            Some(h.rx_evt.clone())
-        } else {
-            None
-        }
+        eng.as_ref().map(|h| h.events())
     };
 
-    if rx.is_none() {
+    let Some(mut events) = events else {
         return;
-    }
-
-    let rx = rx.unwrap();
+    };
 
-    tokio::task::spawn_blocking(move || {
-        while let Ok(evt) = rx.recv() {
-            if let Ok(s) = serde_json::to_string(&evt) {
-                // best-effort; ignore send errors on closed socket
-                let _ = futures::executor::block_on(async { socket.send(Message::Text(s)).await });
+    loop {
+        tokio::select! {
+            evt = events.next() => {
+                let Some(evt) = evt else {
+                    break;
+                };
+                if let Ok(s) = serde_json::to_string(&evt) {
+                    if socket.send(Message::Text(s)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            msg = socket.recv() => {
+                // Only used to notice the client going away
+                if matches!(msg, None | Some(Err(_)) | Some(Ok(Message::Close(_)))) {
+                    break;
+                }
             }
         }
-    });
+    }
 }
@@ -1,9 +1,16 @@
-use nexow_engine::EngineHandle;
+use crate::paths::DataDir;
+use nexow_engine::{EngineHandle, FeedSender};
 use sqlx::PgPool;
 use std::sync::Mutex;
 
 pub struct AppState {
     pub engine: Mutex<Option<EngineHandle>>,
+    /// Where `POST /api/sim/bars` pushes, while a forward test on loaded history runs.
+    pub feed: Mutex<Option<FeedSender>>,
+    pub data_dir: DataDir,
+    // Not queried yet; held so the pools live as long as the server
+    #[allow(dead_code)]
     pub pool_app: PgPool,
+    #[allow(dead_code)]
     pub pool_ts: PgPool,
 }
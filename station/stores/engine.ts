@@ -5,7 +5,15 @@ export const useEngineStore = defineStore('engine', {
     running: false,
     metrics: [] as any[],
     bars: [] as any[],
+    orders: [] as any[],
+    trades: [] as any[],
+    portfolios: [] as any[],
+    report: null as any,
+    error: null as any,
+    status: null as string | null,
     ws: null as WebSocket | null,
+    paused: false,
+    speed: 1,
   }),
   actions: {
     connectWs(url: string) {
@@ -21,6 +29,24 @@ export const useEngineStore = defineStore('engine', {
         if (evt.Metrics) {
           this.metrics.push(evt.Metrics)
         }
+        if (evt.Order) {
+          this.orders.push(evt.Order)
+        }
+        if (evt.Trade) {
+          this.trades.push(evt.Trade)
+        }
+        if (evt.Portfolio) {
+          this.portfolios.push(evt.Portfolio)
+        }
+        if (evt.Summary) {
+          this.report = evt.Summary
+        }
+        if (evt.Error) {
+          this.error = evt.Error
+        }
+        if (evt.Done) {
+          this.status = evt.Done
+        }
       }
       this.ws.onopen = () => {
         console.log('WebSocket connected')
@@ -34,6 +60,21 @@ export const useEngineStore = defineStore('engine', {
         console.error('WebSocket error:', error)
       }
     },
+    async control(apiBaseUrl: string, action: string, extra: Record<string, number> = {}) {
+      await $fetch(apiBaseUrl + '/api/sim/control', {
+        method: 'POST',
+        body: { action, ...extra },
+      })
+      if (action === 'pause' || action === 'step') {
+        this.paused = true
+      }
+      if (action === 'resume') {
+        this.paused = false
+      }
+      if (action === 'speed' && extra.speed) {
+        this.speed = extra.speed
+      }
+    },
     disconnect() {
       if (this.ws) {
         this.ws.close()